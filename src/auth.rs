@@ -1,8 +1,23 @@
 //! Authentication data types and utilities.
 
 use chrono::{DateTime, Utc};
+use json;
 use serde::ser::{Serialize, Serializer};
-use std::fmt::{self, Display};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use super::Result;
+use data::JournalistId;
+use error::{Error, ErrorKind};
+use otp;
+use secret::Secret;
 
 /// Wrapper type for know types of credentials.
 pub enum Credentials {
@@ -10,49 +25,122 @@ pub enum Credentials {
     UserPassTotp(UserPassTotp),
     /// Username, password, HOTP.
     UserPassHotp(UserPassHotp),
+    /// Username, password, and a TOTP shared secret.
+    TotpSecret(TotpSecret),
+    /// Username, password, and an HOTP shared secret with a persistent counter.
+    HotpSecret(HotpSecret),
 }
 
+/// Credentials serialize as their static fields only. `TotpSecret` and `HotpSecret` leave out
+/// the shared secret and never generate a code, so serializing has no side effects.
 impl Serialize for Credentials {
     fn serialize<S>(&self, ser: S) -> ::std::result::Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        match self {
+        match *self {
             Credentials::UserPassTotp(ref c) => c.serialize(ser),
             Credentials::UserPassHotp(ref c) => c.serialize(ser),
+            Credentials::TotpSecret(ref c) => StaticFields {
+                username: &c.username,
+                passphrase: &c.passphrase,
+            }
+            .serialize(ser),
+            Credentials::HotpSecret(ref c) => StaticFields {
+                username: &c.username,
+                passphrase: &c.passphrase,
+            }
+            .serialize(ser),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct StaticFields<'a> {
+    username: &'a str,
+    #[serde(rename = "password")]
+    passphrase: &'a Secret,
+}
+
+impl Credentials {
+    /// The body of the login request. One time codes are generated here so that they are fresh
+    /// when the request is sent.
+    pub(crate) fn login_body(&self) -> Result<LoginBody> {
+        Ok(match *self {
+            Credentials::UserPassTotp(ref c) => LoginBody {
+                username: &c.username,
+                passphrase: &c.passphrase,
+                one_time_code: c.one_time_code.clone(),
+            },
+            Credentials::UserPassHotp(ref c) => LoginBody {
+                username: &c.username,
+                passphrase: &c.passphrase,
+                one_time_code: c.one_time_code.clone(),
+            },
+            Credentials::TotpSecret(ref c) => LoginBody {
+                username: &c.username,
+                passphrase: &c.passphrase,
+                one_time_code: c.next_code()?,
+            },
+            Credentials::HotpSecret(ref c) => LoginBody {
+                username: &c.username,
+                passphrase: &c.passphrase,
+                one_time_code: c.next_code()?,
+            },
+        })
+    }
+
+    /// How many times `Client::reauthorize_with_resync` may retry a rejected login with a fresh
+    /// code.
+    pub(crate) fn resync_attempts(&self) -> u64 {
+        match *self {
+            Credentials::HotpSecret(ref c) => c.resync_window,
+            _ => 0,
         }
     }
 }
 
-/// Authentication via username, passphrase, and TOTP.
+/// The JSON body sent to request a token.
+#[derive(Debug, Serialize)]
+pub(crate) struct LoginBody<'a> {
+    username: &'a str,
+    #[serde(rename = "password")]
+    passphrase: &'a Secret,
+    one_time_code: Secret,
+}
+
+/// Authentication via username, passphrase, and TOTP. The passphrase and code are redacted from
+/// `Debug` output and wiped from memory on drop.
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct UserPassTotp {
     username: String,
     #[serde(rename = "password")]
-    passphrase: String,
-    one_time_code: String,
+    passphrase: Secret,
+    one_time_code: Secret,
 }
 
 impl UserPassTotp {
     /// Construct a new `UserPassTotp` using the given parameters. The OTP value is passed in, and
     /// is reused for each authentication attempt. Because this client will used interactively, we
-    /// don't accept the OTP secret and instead use the initial OTP to get an auth token.
+    /// don't accept the OTP secret and instead use the initial OTP to get an auth token. For
+    /// unattended use, see `TotpSecret`.
     pub fn new(username: String, passphrase: String, one_time_code: String) -> Self {
         Self {
             username,
-            passphrase,
-            one_time_code,
+            passphrase: Secret::new(passphrase),
+            one_time_code: Secret::new(one_time_code),
         }
     }
 }
 
-/// Authentication via username, passphrase, and HOTP.
+/// Authentication via username, passphrase, and HOTP. The passphrase and code are redacted from
+/// `Debug` output and wiped from memory on drop.
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct UserPassHotp {
     username: String,
     #[serde(rename = "password")]
-    passphrase: String,
-    one_time_code: String,
+    passphrase: Secret,
+    one_time_code: Secret,
 }
 
 impl UserPassHotp {
@@ -62,9 +150,289 @@ impl UserPassHotp {
     pub fn new(username: String, passphrase: String, one_time_code: String) -> Self {
         Self {
             username,
-            passphrase,
-            one_time_code,
+            passphrase: Secret::new(passphrase),
+            one_time_code: Secret::new(one_time_code),
+        }
+    }
+}
+
+/// Authentication via username, passphrase, and a base32 TOTP shared secret (as shown when
+/// enrolling a two-factor device). This is intended for unattended service accounts.
+///
+/// A fresh RFC 6238 code is generated each time the client authorizes. SecureDrop rejects a code
+/// that has already been used, so if a code was already generated in the current 30 second
+/// window, authorizing blocks until the next window. The time steps used are tracked in a
+/// `CounterStore` (holding the first step that may still be used), which clones share. Use
+/// `TotpSecret::with_store` and a `FileCounterStore` so a restarted process never reuses a code.
+#[derive(Clone)]
+pub struct TotpSecret {
+    username: String,
+    passphrase: Secret,
+    secret: Secret,
+    steps: Arc<dyn CounterStore>,
+}
+
+impl TotpSecret {
+    /// Construct a new `TotpSecret` that tracks used time steps in memory. This returns an `Err`
+    /// if `secret` is not valid base32.
+    pub fn new(username: String, passphrase: String, secret: String) -> Result<Self> {
+        Self::with_store(username, passphrase, secret, MemoryCounterStore::new(0))
+    }
+
+    /// Construct a new `TotpSecret` that tracks used time steps in `store`. This returns an `Err`
+    /// if `secret` is not valid base32.
+    pub fn with_store<S>(
+        username: String,
+        passphrase: String,
+        secret: String,
+        store: S,
+    ) -> Result<Self>
+    where
+        S: CounterStore + 'static,
+    {
+        let secret = Secret::new(secret);
+        otp::decode_secret(secret.expose_secret())?;
+        Ok(Self {
+            username,
+            passphrase: Secret::new(passphrase),
+            secret,
+            steps: Arc::new(store),
+        })
+    }
+
+    fn next_code(&self) -> Result<Secret> {
+        let key = Zeroizing::new(otp::decode_secret(self.secret.expose_secret())?);
+        let now = otp::now();
+        let step = self.steps.next_from(otp::time_step(now))?;
+        let (_, wait) = next_step(step, now);
+        if wait > 0 {
+            thread::sleep(Duration::from_secs(wait));
+        }
+        Ok(Secret::new(otp::hotp(&key, step)))
+    }
+}
+
+impl fmt::Debug for TotpSecret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TotpSecret")
+            .field("username", &self.username)
+            .field("passphrase", &self.passphrase)
+            .field("secret", &self.secret)
+            .finish()
+    }
+}
+
+/// The TOTP step to use at `now` given the first step that has not been used yet, and how many
+/// seconds to wait for it.
+fn next_step(first_unused: u64, now: u64) -> (u64, u64) {
+    let step = otp::time_step(now);
+    if first_unused > step {
+        (first_unused, first_unused * otp::TIME_STEP - now)
+    } else {
+        (step, 0)
+    }
+}
+
+/// Where the counter of an `HotpSecret` is persisted between logins.
+pub trait CounterStore: Send + Sync {
+    /// Load the counter value to use for the next code.
+    fn load(&self) -> Result<u64>;
+
+    /// Store the counter value to use for the next code.
+    fn save(&self, counter: u64) -> Result<()>;
+
+    /// Atomically take the counter value to use for the next code, which is at least `min`, and
+    /// store the value after it. No two calls ever return the same value.
+    fn next_from(&self, min: u64) -> Result<u64>;
+
+    /// Atomically take the counter value to use for the next code and store the value after it.
+    fn next(&self) -> Result<u64> {
+        self.next_from(0)
+    }
+}
+
+/// A `CounterStore` that only keeps the counter in memory.
+#[derive(Debug, Default)]
+pub struct MemoryCounterStore {
+    counter: Mutex<u64>,
+}
+
+impl MemoryCounterStore {
+    /// Start counting from `counter`.
+    pub fn new(counter: u64) -> Self {
+        Self {
+            counter: Mutex::new(counter),
+        }
+    }
+}
+
+impl CounterStore for MemoryCounterStore {
+    fn load(&self) -> Result<u64> {
+        Ok(*self.counter.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn save(&self, counter: u64) -> Result<()> {
+        *self.counter.lock().unwrap_or_else(|e| e.into_inner()) = counter;
+        Ok(())
+    }
+
+    fn next_from(&self, min: u64) -> Result<u64> {
+        let mut counter = self.counter.lock().unwrap_or_else(|e| e.into_inner());
+        let next = (*counter).max(min);
+        *counter = next + 1;
+        Ok(next)
+    }
+}
+
+/// A `CounterStore` that keeps the counter in a file. A missing file means the counter is `0`. On
+/// Unix, the file is only readable and writable by its owner (mode `0600`), and `next` holds an
+/// exclusive lock on a `.lock` file next to it so that processes sharing the counter never take
+/// the same value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileCounterStore {
+    path: PathBuf,
+}
+
+impl FileCounterStore {
+    /// Store the counter at `path`.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The path of the counter file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl CounterStore for FileCounterStore {
+    fn load(&self) -> Result<u64> {
+        match fs::read_to_string(&self.path) {
+            Ok(counter) => counter
+                .trim()
+                .parse()
+                .map_err(|_| ErrorKind::IO("Invalid HOTP counter file".into()).into()),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    fn save(&self, counter: u64) -> Result<()> {
+        write_private(&self.path, counter.to_string().as_bytes())
+    }
+
+    fn next_from(&self, min: u64) -> Result<u64> {
+        // The counter file itself is replaced on save, so lock a file that never is
+        let mut lock_path = self.path.clone().into_os_string();
+        lock_path.push(".lock");
+        let mut options = OpenOptions::new();
+        options.write(true).create(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
         }
+        let lock = options.open(PathBuf::from(lock_path)).map_err(io_error)?;
+        lock_exclusive(&lock)?;
+
+        let next = self.load()?.max(min);
+        self.save(next + 1)?;
+        // Closing the file releases the lock
+        drop(lock);
+        Ok(next)
+    }
+}
+
+#[cfg(unix)]
+fn lock_exclusive(file: &File) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(());
+        }
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(io_error(e));
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn lock_exclusive(_: &File) -> Result<()> {
+    Ok(())
+}
+
+/// Authentication via username, passphrase, and a base32 HOTP shared secret. This is intended for
+/// test and service accounts that use a software HOTP token.
+///
+/// The counter is taken from a `CounterStore` and advanced before every login attempt, so a code
+/// is never reused even if the attempt fails.
+///
+/// If codes were generated elsewhere, the server's counter may be ahead. Resyncing is opt-in: with
+/// a resync window set (the default is `0`), `Client::reauthorize_with_resync` retries a rejected
+/// login with the following counters. A rejection may also mean a wrong passphrase, in which case
+/// every retry burns a code, and SecureDrop rate limits failed logins, so keep the window small.
+#[derive(Clone)]
+pub struct HotpSecret {
+    username: String,
+    passphrase: Secret,
+    secret: Secret,
+    store: Arc<dyn CounterStore>,
+    resync_window: u64,
+}
+
+impl HotpSecret {
+    /// Construct a new `HotpSecret`. This returns an `Err` if `secret` is not valid base32.
+    pub fn new<S>(username: String, passphrase: String, secret: String, store: S) -> Result<Self>
+    where
+        S: CounterStore + 'static,
+    {
+        let secret = Secret::new(secret);
+        otp::decode_secret(secret.expose_secret())?;
+        Ok(Self {
+            username,
+            passphrase: Secret::new(passphrase),
+            secret,
+            store: Arc::new(store),
+            resync_window: 0,
+        })
+    }
+
+    /// Set how many additional counters `Client::reauthorize_with_resync` may try after a code is
+    /// rejected.
+    pub fn set_resync_window(&mut self, window: u64) {
+        self.resync_window = window;
+    }
+
+    fn next_code(&self) -> Result<Secret> {
+        let key = Zeroizing::new(otp::decode_secret(self.secret.expose_secret())?);
+        let counter = self.store.next()?;
+        Ok(Secret::new(otp::hotp(&key, counter)))
+    }
+}
+
+impl fmt::Debug for HotpSecret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HotpSecret")
+            .field("username", &self.username)
+            .field("passphrase", &self.passphrase)
+            .field("secret", &self.secret)
+            .field("resync_window", &self.resync_window)
+            .finish()
+    }
+}
+
+impl Into<Credentials> for HotpSecret {
+    fn into(self) -> Credentials {
+        Credentials::HotpSecret(self)
+    }
+}
+
+impl Into<Credentials> for TotpSecret {
+    fn into(self) -> Credentials {
+        Credentials::TotpSecret(self)
     }
 }
 
@@ -86,23 +454,148 @@ pub(crate) enum Authorization {
     Token(AuthToken),
 }
 
-/// The return value from the API.
-#[derive(Debug, Clone, PartialEq, Deserialize)]
-pub(crate) struct AuthToken {
-    token: String,
+/// An API token along with when it expires and who it belongs to. Tokens can be saved (e.g., with
+/// a `TokenStore`) and later passed to `Client::from_token` to avoid logging in again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthToken {
+    token: Secret,
     expires: DateTime<Utc>,
+    #[serde(default)]
+    journalist_uuid: Option<JournalistId>,
 }
 
-impl Display for AuthToken {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        Display::fmt(&self.token, f)
+impl AuthToken {
+    /// The token sent in the `Authorization` header.
+    pub fn token(&self) -> &Secret {
+        &self.token
+    }
+
+    /// When the token expires.
+    pub fn expires(&self) -> &DateTime<Utc> {
+        &self.expires
+    }
+
+    /// The ID of the journalist the token was issued to. Older servers do not return this.
+    pub fn journalist_uuid(&self) -> Option<&JournalistId> {
+        self.journalist_uuid.as_ref()
+    }
+
+    /// Whether the token has expired.
+    pub fn is_expired(&self) -> bool {
+        self.expires <= Utc::now()
+    }
+}
+
+/// Somewhere an `AuthToken` can be persisted between runs.
+pub trait TokenStore {
+    /// Load the stored token, if there is one. Expired tokens are returned as is.
+    fn load(&self) -> Result<Option<AuthToken>>;
+
+    /// Store a token, replacing any existing one.
+    fn save(&self, token: &AuthToken) -> Result<()>;
+
+    /// Remove the stored token (e.g., on logout).
+    fn clear(&self) -> Result<()>;
+}
+
+/// A `TokenStore` that keeps the token in a JSON file. On Unix, the file is only readable and
+/// writable by its owner (mode `0600`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileTokenStore {
+    path: PathBuf,
+}
+
+impl FileTokenStore {
+    /// Store the token at `path`.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The path of the token file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self) -> Result<Option<AuthToken>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(io_error(e)),
+        };
+        json::from_reader(BufReader::new(file))
+            .map(Some)
+            .map_err(|e| ErrorKind::IO(format!("Invalid token file: {}", e)).into())
+    }
+
+    fn save(&self, token: &AuthToken) -> Result<()> {
+        let token = Zeroizing::new(
+            json::to_vec(token).map_err(|e| ErrorKind::ProgrammingError(e.to_string()))?,
+        );
+        write_private(&self.path, &token)
+    }
+
+    fn clear(&self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            x => x.map_err(io_error),
+        }
+    }
+}
+
+/// Write a file that only its owner can read, replacing it atomically so that a crash never leaves
+/// it truncated.
+pub(crate) fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    // A fresh, unpredictable name means another process (or an attacker who planted a symlink)
+    // can never have the temporary file open
+    let mut tmp = path.to_path_buf().into_os_string();
+    tmp.push(format!(".{}.tmp", Uuid::new_v4().simple()));
+    let tmp = PathBuf::from(tmp);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
     }
+    let mut file = options.open(&tmp).map_err(io_error)?;
+    let written = file
+        .write_all(contents)
+        .and_then(|_| file.sync_all())
+        .and_then(|_| fs::rename(&tmp, path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(io_error(e));
+    }
+    Ok(())
+}
+
+/// Create a directory (and its parents) that only its owner can access.
+pub(crate) fn create_private_dir(path: &Path) -> Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(path).map_err(io_error)
+}
+
+fn io_error(e: io::Error) -> Error {
+    ErrorKind::IO(e.to_string()).into()
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use json;
+    use chrono::Duration;
+    use std::env;
+    use transport::{Method, Request};
 
     // This is a sanity check beacuse serializing these use different codepaths
     #[test]
@@ -122,4 +615,187 @@ mod test {
 
         assert_eq!(totp_keys, hotp_keys);
     }
+
+    #[test]
+    fn login_body_fields_equal() {
+        let totp: Credentials =
+            UserPassTotp::new("user".into(), "pass".into(), "DEADBEEF".into()).into();
+        let secret: Credentials =
+            TotpSecret::new("user".into(), "pass".into(), "JBSWY3DPEHPK3PXP".into())
+                .unwrap()
+                .into();
+
+        let totp_value = json::to_value(totp.login_body().unwrap()).unwrap();
+        let totp_value = totp_value.as_object().unwrap();
+        let totp_keys: Vec<_> = totp_value.keys().collect();
+
+        let secret_value = json::to_value(secret.login_body().unwrap()).unwrap();
+        let secret_value = secret_value.as_object().unwrap();
+        let secret_keys: Vec<_> = secret_value.keys().collect();
+
+        assert_eq!(totp_keys, secret_keys);
+    }
+
+    #[test]
+    fn serializing_secrets_uses_no_codes() {
+        let hotp: Credentials = HotpSecret::new(
+            "user".into(),
+            "pass".into(),
+            "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".into(),
+            MemoryCounterStore::new(7),
+        )
+        .unwrap()
+        .into();
+
+        let value = json::to_value(&hotp).unwrap();
+        let keys: Vec<_> = value.as_object().unwrap().keys().collect();
+        assert_eq!(keys, vec!["password", "username"]);
+        json::to_value(&hotp).unwrap();
+
+        // The counter is where it started
+        let body = json::to_value(hotp.login_body().unwrap()).unwrap();
+        assert_eq!(body["one_time_code"], "162583");
+    }
+
+    #[test]
+    fn totp_codes_are_not_reused() {
+        assert_eq!(next_step(0, 59), (1, 0));
+        assert_eq!(next_step(1, 59), (1, 0));
+        assert_eq!(next_step(2, 59), (2, 1));
+        assert_eq!(next_step(2, 31), (2, 29));
+    }
+
+    #[test]
+    fn totp_steps_persist() {
+        let path = env::temp_dir().join(format!("securedrop-steps-test-{}", ::std::process::id()));
+        let store = FileCounterStore::new(&path);
+        let step = otp::time_step(otp::now());
+        store.save(step).unwrap();
+
+        let creds = TotpSecret::with_store(
+            "user".into(),
+            "pass".into(),
+            "JBSWY3DPEHPK3PXP".into(),
+            store.clone(),
+        );
+        creds.unwrap().next_code().unwrap();
+
+        // A restarted process sees that the step was used
+        let first_unused = FileCounterStore::new(&path).load().unwrap();
+        assert!(first_unused > step);
+        assert!(next_step(first_unused, otp::now()).0 > step);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn hotp_counter_advances() {
+        let mut secret = HotpSecret::new(
+            "user".into(),
+            "pass".into(),
+            "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".into(),
+            MemoryCounterStore::new(1),
+        )
+        .unwrap();
+        secret.set_resync_window(3);
+        let creds: Credentials = secret.into();
+        let code = |c: &Credentials| {
+            json::to_value(c.login_body().unwrap()).unwrap()["one_time_code"].clone()
+        };
+        assert_eq!(code(&creds), "287082");
+        assert_eq!(code(&creds), "359152");
+        assert_eq!(creds.resync_attempts(), 3);
+    }
+
+    #[test]
+    fn file_counter_store_is_atomic() {
+        let path =
+            env::temp_dir().join(format!("securedrop-counter-test-{}", ::std::process::id()));
+        let store = FileCounterStore::new(&path);
+        store.save(5).unwrap();
+        assert_eq!(store.next().unwrap(), 5);
+        assert_eq!(store.next_from(10).unwrap(), 10);
+        assert_eq!(store.next_from(3).unwrap(), 11);
+
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let store = store.clone();
+                thread::spawn(move || (0..25).map(|_| store.next().unwrap()).collect::<Vec<_>>())
+            })
+            .collect();
+        let mut taken: Vec<u64> = threads
+            .into_iter()
+            .flat_map(|t| t.join().unwrap())
+            .collect();
+        taken.sort();
+        assert_eq!(taken, (12..112).collect::<Vec<_>>());
+
+        fs::remove_file(&path).unwrap();
+        let mut lock = path.into_os_string();
+        lock.push(".lock");
+        fs::remove_file(&lock).unwrap();
+    }
+
+    #[test]
+    fn file_token_store() {
+        let path = env::temp_dir().join(format!("securedrop-token-test-{}", ::std::process::id()));
+        let store = FileTokenStore::new(&path);
+        assert_eq!(store.load().unwrap(), None);
+
+        let token = AuthToken {
+            token: Secret::new("abc123".into()),
+            expires: Utc::now() + Duration::hours(8),
+            journalist_uuid: None,
+        };
+        store.save(&token).unwrap();
+        store.save(&token).unwrap();
+        assert_eq!(store.load().unwrap(), Some(token));
+
+        // Replacing the file leaves no temporary files behind
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        let leftovers = fs::read_dir(env::temp_dir())
+            .unwrap()
+            .filter(|e| {
+                let e = e.as_ref().unwrap().file_name();
+                e.to_str().unwrap_or("").starts_with(&format!("{}.", name))
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        store.clear().unwrap();
+        assert_eq!(store.load().unwrap(), None);
+    }
+
+    #[test]
+    fn debug_redacts_secrets() {
+        let creds = UserPassTotp::new("user".into(), "hunter2".into(), "123456".into());
+        let debug = format!("{:?}", creds);
+        assert!(debug.contains("user"));
+        assert!(!debug.contains("hunter2"));
+        assert!(!debug.contains("123456"));
+    }
+
+    #[test]
+    fn debug_redacts_requests() {
+        let creds: Credentials =
+            UserPassTotp::new("user".into(), "hunter2".into(), "123456".into()).into();
+        let url = "http://localhost:8081/api/v1/token".parse().unwrap();
+        let mut req = Request::new(Method::Post, url);
+        req.set_header("Authorization", "Token abc123");
+        req.set_header("Content-Type", "application/json");
+        req.set_body(json::to_vec(&creds.login_body().unwrap()).unwrap());
+
+        let debug = format!("{:?}", req);
+        assert!(debug.contains("application/json"));
+        assert!(debug.contains("[REDACTED]"));
+        assert!(!debug.contains("abc123"));
+        assert!(!debug.contains("hunter2"));
+        assert!(!debug.contains("123456"));
+    }
 }
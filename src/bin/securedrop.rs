@@ -0,0 +1,556 @@
+//! Command line interface for scripting SecureDrop operations.
+//!
+//! Build with `cargo build --features cli`. Credentials are taken from the command line or the
+//! environment (`SECUREDROP_URL`, `SECUREDROP_USERNAME` and `SECUREDROP_PASSPHRASE`), and anything
+//! missing is prompted for.
+
+#[macro_use]
+extern crate clap;
+extern crate rpassword;
+extern crate securedrop_api;
+extern crate serde;
+#[macro_use]
+extern crate serde_json as json;
+extern crate uuid;
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use securedrop_api::auth::{FileTokenStore, TokenStore, UserPassTotp};
+use securedrop_api::data::{Reply, SourceId, Submission, SubmissionId};
+use securedrop_api::{Client, ErrorKind, Result};
+use serde::Serialize;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use uuid::Uuid;
+
+fn app() -> App<'static, 'static> {
+    let source = || {
+        Arg::with_name("source")
+            .help("UUID of the source")
+            .required(true)
+    };
+    let submission = || {
+        Arg::with_name("submission")
+            .help("UUID of the submission")
+            .required(true)
+    };
+
+    App::new("securedrop")
+        .version(crate_version!())
+        .about("Interact with a SecureDrop server from the command line")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("url")
+                .long("url")
+                .env("SECUREDROP_URL")
+                .default_value("http://localhost:8081")
+                .global(true)
+                .help("Base URL of the SecureDrop server"),
+        )
+        .arg(
+            Arg::with_name("username")
+                .long("username")
+                .short("u")
+                .env("SECUREDROP_USERNAME")
+                .takes_value(true)
+                .global(true)
+                .help("Journalist username"),
+        )
+        .arg(
+            Arg::with_name("otp")
+                .long("otp")
+                .takes_value(true)
+                .global(true)
+                .help("One time code (prompted for if not given)"),
+        )
+        .arg(
+            Arg::with_name("token-file")
+                .long("token-file")
+                .env("SECUREDROP_TOKEN_FILE")
+                .takes_value(true)
+                .global(true)
+                .help("Reuse the session token stored in this file, and store new tokens there"),
+        )
+        .arg(
+            Arg::with_name("json")
+                .long("json")
+                .global(true)
+                .help("Print machine readable JSON instead of tables"),
+        )
+        .subcommand(
+            SubCommand::with_name("login")
+                .about("Log in, storing the session token if --token-file is set"),
+        )
+        .subcommand(SubCommand::with_name("whoami").about("Show the logged in user"))
+        .subcommand(SubCommand::with_name("sources").about("List all sources"))
+        .subcommand(
+            SubCommand::with_name("submissions")
+                .about("List a source's submissions")
+                .arg(source()),
+        )
+        .subcommand(
+            SubCommand::with_name("download")
+                .about("Download a submission")
+                .arg(source())
+                .arg(submission())
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .help(
+                            "File to write to (defaults to the submission's filename in the \
+                             current directory)",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .short("f")
+                        .help("Overwrite the output file if it exists"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reply")
+                .about("Send a pre-encrypted reply to a source")
+                .arg(source())
+                .arg(
+                    Arg::with_name("file")
+                        .help("File containing the ASCII armored reply")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("star")
+                .about("Star a source")
+                .arg(source()),
+        )
+        .subcommand(
+            SubCommand::with_name("unstar")
+                .about("Unstar a source")
+                .arg(source()),
+        )
+        .subcommand(
+            SubCommand::with_name("delete")
+                .about("Delete a source, or only one of its submissions")
+                .arg(source())
+                .arg(
+                    Arg::with_name("submission")
+                        .help("UUID of the submission (deletes the whole source if omitted)"),
+                )
+                .arg(
+                    Arg::with_name("yes")
+                        .long("yes")
+                        .short("y")
+                        .help("Confirm deleting a whole source"),
+                ),
+        )
+}
+
+fn main() {
+    let matches = app().get_matches();
+    if let Err(e) = run(&matches) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<()> {
+    let (name, sub) = match matches.subcommand() {
+        (name, Some(sub)) => (name, sub),
+        _ => unreachable!("subcommand is required"),
+    };
+    check(name, sub)?;
+    let client = login(sub, name == "login")?;
+    let out = Output {
+        json: sub.is_present("json"),
+    };
+
+    match name {
+        "login" | "whoami" => {
+            let user = client.user()?;
+            if name == "login" && !out.json {
+                println!("Logged in as {}", user.username());
+                return Ok(());
+            }
+            out.print(
+                &user,
+                &["USERNAME", "UUID", "ADMIN", "LAST LOGIN"],
+                vec![vec![
+                    user.username().to_string(),
+                    user.uuid().map(|u| u.to_string()).unwrap_or_default(),
+                    user.is_admin().to_string(),
+                    user.last_login().to_rfc3339(),
+                ]],
+            )
+        }
+        "sources" => {
+            let sources = client.sources()?;
+            let rows = sources
+                .sources()
+                .iter()
+                .map(|s| {
+                    vec![
+                        s.uuid().to_string(),
+                        s.journalist_designation().to_string(),
+                        s.number_of_messages()
+                            .map(|n| n.to_string())
+                            .unwrap_or_default(),
+                        s.number_of_documents()
+                            .map(|n| n.to_string())
+                            .unwrap_or_default(),
+                        s.last_updated().to_rfc3339(),
+                    ]
+                })
+                .collect();
+            out.print(
+                &sources,
+                &[
+                    "UUID",
+                    "DESIGNATION",
+                    "MESSAGES",
+                    "DOCUMENTS",
+                    "LAST UPDATED",
+                ],
+                rows,
+            )
+        }
+        "submissions" => {
+            let submissions = client.source_submissions(&source_id(sub)?)?;
+            let rows = submissions
+                .chronological()
+                .iter()
+                .map(|s| {
+                    vec![
+                        s.uuid().to_string(),
+                        s.filename().to_string(),
+                        s.size().to_string(),
+                        s.is_read().to_string(),
+                    ]
+                })
+                .collect();
+            out.print(&submissions, &["UUID", "FILENAME", "SIZE", "READ"], rows)
+        }
+        "download" => {
+            let source = source_id(sub)?;
+            let submission = submission_id(sub)?;
+            let path = match sub.value_of("output") {
+                Some(path) => path.to_string(),
+                None => default_output(&client.source_submission(&source, &submission)?),
+            };
+            download_to(Path::new(&path), sub.is_present("force"), |file| {
+                client.download_submission(&source, &submission, file)
+            })?;
+            out.message(&json!({ "path": path }), &format!("Downloaded to {}", path))
+        }
+        "reply" => {
+            let body = fs::read_to_string(sub.value_of("file").unwrap()).map_err(io_error)?;
+            let resp = client.reply_to_source(&source_id(sub)?, &Reply::new(body)?)?;
+            out.message(&resp, resp.message())
+        }
+        "star" => {
+            let resp = client.star_source(&source_id(sub)?)?;
+            out.message(&resp, resp.message())
+        }
+        "unstar" => {
+            let resp = client.unstar_source(&source_id(sub)?)?;
+            out.message(&resp, resp.message())
+        }
+        "delete" => {
+            let source = source_id(sub)?;
+            let resp = match sub.value_of("submission") {
+                Some(_) => client.delete_source_submission(&source, &submission_id(sub)?)?,
+                None => client.delete_submissions(&source)?,
+            };
+            out.message(&resp, resp.message())
+        }
+        _ => unreachable!("unknown subcommand"),
+    }
+}
+
+/// Reject commands that should not reach the server as given.
+fn check(name: &str, matches: &ArgMatches) -> Result<()> {
+    if name == "delete" && !matches.is_present("submission") && !matches.is_present("yes") {
+        let msg = "Deleting a whole source needs --yes";
+        return Err(ErrorKind::ClientError(msg.into()).into());
+    }
+    Ok(())
+}
+
+/// The file to download a submission to when none is given. Filenames from the server are only
+/// used if they are a plain name, so a download can never land outside the current directory.
+fn default_output(submission: &Submission) -> String {
+    let filename = Path::new(submission.filename());
+    match filename.file_name() {
+        Some(name) if name == filename.as_os_str() => submission.filename().to_string(),
+        _ => submission.uuid().to_string(),
+    }
+}
+
+/// Download into a temporary file that only its owner can read, next to `path`, and move it into
+/// place once complete, so a failed download never touches an existing file. Unless `overwrite`
+/// is set, an existing file at `path` is an error.
+fn download_to<F>(path: &Path, overwrite: bool, download: F) -> Result<()>
+where
+    F: FnOnce(&mut File) -> Result<()>,
+{
+    if !overwrite && path.exists() {
+        let msg = format!(
+            "{} already exists, use --force to overwrite it",
+            path.display()
+        );
+        return Err(ErrorKind::ClientError(msg).into());
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.part", Uuid::new_v4().simple()));
+    let tmp = PathBuf::from(tmp);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&tmp).map_err(io_error)?;
+    let result = download(&mut file)
+        .and_then(|_| file.sync_all().map_err(io_error))
+        .and_then(|_| {
+            if overwrite {
+                fs::rename(&tmp, path).map_err(io_error)
+            } else {
+                // Unlike a rename, linking fails if the file appeared during the download
+                fs::hard_link(&tmp, path).map_err(io_error)
+            }
+        });
+    if result.is_err() || !overwrite {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+fn login(matches: &ArgMatches, force: bool) -> Result<Client> {
+    let url = matches
+        .value_of("url")
+        .unwrap()
+        .parse()
+        .map_err(|_| ErrorKind::ClientError("Invalid URL".into()))?;
+    let user_agent = format!("securedrop-cli/{}", crate_version!());
+    let store = matches.value_of("token-file").map(FileTokenStore::new);
+
+    if let Some(ref store) = store {
+        match store.load()? {
+            Some(token) if !force && !token.is_expired() => {
+                return Client::from_token(url, token, Some(user_agent))
+            }
+            _ => (),
+        }
+    }
+
+    let username = match matches.value_of("username") {
+        Some(username) => username.to_string(),
+        None => prompt("Username: ")?,
+    };
+    let passphrase = match env::var("SECUREDROP_PASSPHRASE") {
+        Ok(passphrase) => passphrase,
+        Err(_) => rpassword::prompt_password_stderr("Passphrase: ").map_err(io_error)?,
+    };
+    let otp = match matches.value_of("otp") {
+        Some(otp) => otp.to_string(),
+        None => prompt("One time code: ")?,
+    };
+    let client = Client::new(
+        url,
+        UserPassTotp::new(username, passphrase, otp),
+        Some(user_agent),
+    )?;
+    if let (Some(store), Some(token)) = (store, client.auth_token()) {
+        store.save(token)?;
+    }
+    Ok(client)
+}
+
+fn prompt(msg: &str) -> Result<String> {
+    eprint!("{}", msg);
+    io::stderr().flush().map_err(io_error)?;
+    let mut line = String::new();
+    let stdin = io::stdin();
+    stdin.lock().read_line(&mut line).map_err(io_error)?;
+    Ok(line.trim().to_string())
+}
+
+fn source_id(matches: &ArgMatches) -> Result<SourceId> {
+    matches.value_of("source").unwrap().parse()
+}
+
+fn submission_id(matches: &ArgMatches) -> Result<SubmissionId> {
+    matches.value_of("submission").unwrap().parse()
+}
+
+fn io_error(e: io::Error) -> securedrop_api::Error {
+    ErrorKind::IO(e.to_string()).into()
+}
+
+struct Output {
+    json: bool,
+}
+
+impl Output {
+    /// Print either the JSON form of `value` or a table of `rows`.
+    fn print<T>(&self, value: &T, headers: &[&str], rows: Vec<Vec<String>>) -> Result<()>
+    where
+        T: Serialize,
+    {
+        if self.json {
+            return self.print_json(value);
+        }
+
+        let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let headers = headers.iter().map(|h| h.to_string()).collect();
+        for row in Some(headers).into_iter().chain(rows) {
+            let line = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join("  ");
+            println!("{}", line.trim_end());
+        }
+        Ok(())
+    }
+
+    /// Print either the JSON form of `value` or a one line message.
+    fn message<T: Serialize>(&self, value: &T, msg: &str) -> Result<()> {
+        if self.json {
+            self.print_json(value)
+        } else {
+            println!("{}", msg);
+            Ok(())
+        }
+    }
+
+    fn print_json<T: Serialize>(&self, value: &T) -> Result<()> {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        json::to_writer_pretty(&mut stdout, value).map_err(|e| ErrorKind::IO(e.to_string()))?;
+        writeln!(stdout).map_err(io_error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn submission(filename: &str) -> Submission {
+        json::from_value(json!({
+            "uuid": "2a6c0f5e-8a55-4a3f-b8a0-2a0c1b2f1f4d",
+            "source_uuid": "8c3b4d6e-1c4e-4d2a-9a0b-6b1f2f2c7e11",
+            "filename": filename,
+            "is_read": false,
+            "size": 604,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn default_output_is_a_plain_name() {
+        let name = "1-uninteresting_agglutination-msg.gpg";
+        assert_eq!(default_output(&submission(name)), name);
+        for name in &["/etc/passwd", "../1-msg.gpg", "dir/1-msg.gpg", "..", ""] {
+            assert_eq!(
+                default_output(&submission(name)),
+                "2a6c0f5e-8a55-4a3f-b8a0-2a0c1b2f1f4d"
+            );
+        }
+    }
+
+    #[test]
+    fn download_keeps_existing_files() {
+        let dir = env::temp_dir().join(format!("securedrop-cli-test-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("1-msg.gpg");
+        let write = |contents: &'static [u8]| {
+            move |file: &mut File| file.write_all(contents).map_err(io_error)
+        };
+
+        download_to(&path, false, write(b"first")).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // Neither refusing to overwrite nor a failed download touches the existing file
+        let err = download_to(&path, false, write(b"second")).unwrap_err();
+        assert!(err.to_string().contains("already exists"));
+        let failed = download_to(&path, true, |file| {
+            file.write_all(b"partial").map_err(io_error)?;
+            Err(ErrorKind::NetworkError.into())
+        });
+        assert!(failed.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+
+        download_to(&path, true, write(b"second")).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn delete_source_needs_confirmation() {
+        let source = "8c3b4d6e-1c4e-4d2a-9a0b-6b1f2f2c7e11";
+        let submission = "2a6c0f5e-8a55-4a3f-b8a0-2a0c1b2f1f4d";
+        let check_args = |args: &[&str]| {
+            let matches = app()
+                .get_matches_from_safe(Some("securedrop").into_iter().chain(args.iter().cloned()))
+                .unwrap();
+            let (name, sub) = matches.subcommand();
+            check(name, sub.unwrap())
+        };
+
+        let err = check_args(&["delete", source]).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::ClientError("Deleting a whole source needs --yes".into())
+        );
+        check_args(&["delete", "--yes", source]).unwrap();
+        check_args(&["delete", source, submission]).unwrap();
+        check_args(&["sources"]).unwrap();
+    }
+
+    #[test]
+    fn parse_arguments() {
+        let matches = app()
+            .get_matches_from_safe(vec![
+                "securedrop",
+                "--json",
+                "download",
+                "a",
+                "b",
+                "-o",
+                "x",
+            ])
+            .unwrap();
+        assert_eq!(matches.subcommand_name(), Some("download"));
+        let sub = matches.subcommand_matches("download").unwrap();
+        assert!(sub.is_present("json"));
+        assert_eq!(sub.value_of("output"), Some("x"));
+
+        assert!(app()
+            .get_matches_from_safe(vec!["securedrop", "download", "a"])
+            .is_err());
+        assert!(app()
+            .get_matches_from_safe(vec!["securedrop", "frobnicate"])
+            .is_err());
+    }
+}
@@ -0,0 +1,406 @@
+//! Run many requests concurrently.
+//!
+//! `Bulk` runs a batch of requests on a small pool of worker threads. Tor limits how many
+//! circuits are worth opening at once, so the number of workers is configurable and defaults to
+//! `DEFAULT_CONCURRENCY`. Items that fail with a network error or a `502`, `503` or `504` response
+//! from a gateway can be retried. Every
+//! item gets its own result, and a progress callback is called as each one finishes. By default a
+//! failure does not stop the batch; with `fail_fast` the remaining items are skipped after the
+//! first failure.
+//!
+//! ```no_run
+//! # extern crate securedrop_api;
+//! use securedrop_api::auth::UserPassTotp;
+//! use securedrop_api::bulk::Bulk;
+//! use securedrop_api::Client;
+//!
+//! # fn main() {
+//! # let creds = UserPassTotp::new("journalist".into(), "pass".into(), "123456".into());
+//! let client = Client::new("http://localhost:8081".parse().unwrap(), creds, None).unwrap();
+//! let source = *client.sources().unwrap().sources()[0].uuid();
+//! let submissions = client.source_submissions(&source).unwrap();
+//! let unread: Vec<_> = submissions
+//!     .submissions()
+//!     .iter()
+//!     .filter(|s| !s.is_read())
+//!     .cloned()
+//!     .collect();
+//!
+//! let result = Bulk::new(&client)
+//!     .concurrency(4)
+//!     .on_progress(|p| println!("{}/{}", p.completed(), p.total()))
+//!     .download_many(&unread, "downloads");
+//! for (id, err) in result.failed() {
+//!     println!("{} failed: {}", id, err);
+//! }
+//! # }
+//! ```
+
+use crossbeam_utils::thread as scoped;
+use std::fs::{self, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use super::Result;
+use client::Client;
+use data::{Response, Seen, SourceId, Submission, SubmissionId};
+use error::{Error, ErrorKind};
+
+/// The number of concurrent requests used unless `Bulk::concurrency` is set.
+pub const DEFAULT_CONCURRENCY: usize = 2;
+
+/// The delay before the first retry. Each further retry waits twice as long as the last.
+const RETRY_DELAY_MS: u64 = 500;
+
+/// How far a batch has got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+}
+
+impl Progress {
+    /// The number of items in the batch.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// The number of items that succeeded.
+    pub fn succeeded(&self) -> usize {
+        self.succeeded
+    }
+
+    /// The number of items that failed.
+    pub fn failed(&self) -> usize {
+        self.failed
+    }
+
+    /// The number of items that have finished.
+    pub fn completed(&self) -> usize {
+        self.succeeded + self.failed
+    }
+}
+
+/// The results of a batch, in the order the items were given.
+#[derive(Debug)]
+pub struct BulkResult<K, T> {
+    results: Vec<(K, Result<T>)>,
+    skipped: Vec<K>,
+}
+
+impl<K, T> BulkResult<K, T> {
+    /// The result of every item that was attempted.
+    pub fn results(&self) -> &[(K, Result<T>)] {
+        &self.results
+    }
+
+    /// Items that were not attempted because an earlier item failed in fail fast mode.
+    pub fn skipped(&self) -> &[K] {
+        &self.skipped
+    }
+
+    /// Items that succeeded.
+    pub fn succeeded(&self) -> Vec<(&K, &T)> {
+        self.results
+            .iter()
+            .filter_map(|(k, r)| r.as_ref().ok().map(|t| (k, t)))
+            .collect()
+    }
+
+    /// Items that failed.
+    pub fn failed(&self) -> Vec<(&K, &Error)> {
+        self.results
+            .iter()
+            .filter_map(|(k, r)| r.as_ref().err().map(|e| (k, e)))
+            .collect()
+    }
+
+    /// Whether every item was attempted and succeeded.
+    pub fn is_success(&self) -> bool {
+        self.skipped.is_empty() && self.results.iter().all(|(_, r)| r.is_ok())
+    }
+
+    /// Consume the batch and return the results and skipped items.
+    pub fn into_parts(self) -> (Vec<(K, Result<T>)>, Vec<K>) {
+        (self.results, self.skipped)
+    }
+}
+
+type ProgressFn<'a> = Box<dyn Fn(&Progress) + Sync + 'a>;
+
+/// Runs batches of requests concurrently.
+pub struct Bulk<'a> {
+    client: &'a Client,
+    concurrency: usize,
+    retries: u32,
+    fail_fast: bool,
+    progress: Option<ProgressFn<'a>>,
+}
+
+impl<'a> Bulk<'a> {
+    /// Run batches with the given client.
+    pub fn new(client: &'a Client) -> Self {
+        Self {
+            client,
+            concurrency: DEFAULT_CONCURRENCY,
+            retries: 0,
+            fail_fast: false,
+            progress: None,
+        }
+    }
+
+    /// Set the maximum number of concurrent requests (at least 1).
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Retry each item up to `retries` times if it fails with a network error or a `502 Bad
+    /// Gateway`, `503 Service Unavailable` or `504 Gateway Timeout` response. Other errors (e.g.,
+    /// an unknown source or a full disk) are not retried. A delete that is retried counts as a
+    /// success if the item is gone (`404 Not Found`), since an earlier attempt may have gone
+    /// through even though its response was lost.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Skip the remaining items after the first failure. Requests already in flight finish.
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Call `func` each time an item finishes. Calls are never concurrent.
+    pub fn on_progress<F>(mut self, func: F) -> Self
+    where
+        F: Fn(&Progress) + Sync + 'a,
+    {
+        self.progress = Some(Box::new(func));
+        self
+    }
+
+    /// Download submissions into the directory `dest` (which is created if needed), each named
+    /// after its SecureDrop filename. Files are written to a temporary file and renamed into
+    /// place once complete.
+    pub fn download_many<P>(
+        &self,
+        submissions: &[Submission],
+        dest: P,
+    ) -> BulkResult<SubmissionId, PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        let dest = dest.as_ref();
+        let created = fs::create_dir_all(dest);
+        self.run(
+            submissions,
+            |s| *s.uuid(),
+            |s, _| match created {
+                Ok(()) => self.download(s, dest),
+                Err(ref e) => Err(ErrorKind::IO(e.to_string()).into()),
+            },
+        )
+    }
+
+    /// Star sources.
+    pub fn star_sources(&self, sources: &[SourceId]) -> BulkResult<SourceId, Response> {
+        self.run(sources, |s| *s, |s, _| self.client.star_source(s))
+    }
+
+    /// Remove the stars from sources.
+    pub fn unstar_sources(&self, sources: &[SourceId]) -> BulkResult<SourceId, Response> {
+        self.run(sources, |s| *s, |s, _| self.client.unstar_source(s))
+    }
+
+    /// Delete sources and all their submissions.
+    pub fn delete_sources(&self, sources: &[SourceId]) -> BulkResult<SourceId, Response> {
+        self.run(
+            sources,
+            |s| *s,
+            |s, attempt| deleted(attempt, self.client.delete_submissions(s)),
+        )
+    }
+
+    /// Delete submissions, given with the source they belong to.
+    pub fn delete_submissions(
+        &self,
+        submissions: &[(SourceId, SubmissionId)],
+    ) -> BulkResult<SubmissionId, Response> {
+        self.run(
+            submissions,
+            |&(_, id)| id,
+            |(source, id), attempt| {
+                deleted(attempt, self.client.delete_source_submission(source, id))
+            },
+        )
+    }
+
+    /// Mark submissions as seen by the current user. Each submission is sent separately so that
+    /// one that has been deleted does not cause the others to be rejected.
+    pub fn mark_seen(&self, submissions: &[Submission]) -> BulkResult<SubmissionId, Response> {
+        self.run(
+            submissions,
+            |s| *s.uuid(),
+            |s, _| {
+                let mut seen = Seen::new();
+                seen.add_submission(s)?;
+                self.client.mark_seen(&seen)
+            },
+        )
+    }
+
+    fn download(&self, submission: &Submission, dest: &Path) -> Result<PathBuf> {
+        let filename = Path::new(submission.filename());
+        // Never trust a filename from the server with anything other than a plain name
+        let filename = match filename.file_name() {
+            Some(name) if name == filename.as_os_str() => name,
+            _ => {
+                let msg = format!("Invalid filename: {}", submission.filename());
+                return Err(ErrorKind::ClientError(msg).into());
+            }
+        };
+        let path = dest.join(filename);
+        let tmp = dest.join(format!(".{}.part", submission.uuid()));
+
+        // Submissions are only for the journalist's eyes, so keep them from other local users
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let result = options
+            .open(&tmp)
+            .map_err(|e| ErrorKind::IO(e.to_string()).into())
+            .and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                self.client.download_submission(
+                    submission.source_uuid(),
+                    submission.uuid(),
+                    &mut writer,
+                )?;
+                writer.flush().map_err(|e| ErrorKind::IO(e.to_string()))?;
+                fs::rename(&tmp, &path).map_err(|e| ErrorKind::IO(e.to_string()).into())
+            });
+        match result {
+            Ok(()) => Ok(path),
+            Err(e) => {
+                let _ = fs::remove_file(&tmp);
+                Err(e)
+            }
+        }
+    }
+
+    /// Run `task` until it succeeds, fails with an error that is not transient, or runs out of
+    /// retries. `task` is given the number of the attempt, starting from `0`.
+    fn retry<R, F>(&self, task: F) -> Result<R>
+    where
+        F: Fn(u32) -> Result<R>,
+    {
+        let mut delay = Duration::from_millis(RETRY_DELAY_MS);
+        let mut attempt = 0;
+        loop {
+            match task(attempt) {
+                Err(ref e) if attempt < self.retries && is_transient(e) => (),
+                result => return result,
+            }
+            thread::sleep(delay);
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+
+    /// Run `task` for every item on the worker pool.
+    fn run<T, K, R, G, F>(&self, items: &[T], key: G, task: F) -> BulkResult<K, R>
+    where
+        T: Sync,
+        R: Send,
+        G: Fn(&T) -> K,
+        F: Fn(&T, u32) -> Result<R> + Sync,
+    {
+        let next = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        let results: Mutex<Vec<Option<Result<R>>>> =
+            Mutex::new(items.iter().map(|_| None).collect());
+        let progress = Mutex::new(Progress {
+            total: items.len(),
+            succeeded: 0,
+            failed: 0,
+        });
+
+        let scoped = scoped::scope(|scope| {
+            for _ in 0..self.concurrency.min(items.len()) {
+                scope.spawn(|_| {
+                    while !stop.load(Ordering::SeqCst) {
+                        let idx = next.fetch_add(1, Ordering::SeqCst);
+                        let item = match items.get(idx) {
+                            Some(item) => item,
+                            None => break,
+                        };
+                        let result = self.retry(|attempt| task(item, attempt));
+
+                        let mut progress = progress.lock().unwrap_or_else(|e| e.into_inner());
+                        if result.is_ok() {
+                            progress.succeeded += 1;
+                        } else {
+                            progress.failed += 1;
+                            if self.fail_fast {
+                                stop.store(true, Ordering::SeqCst);
+                            }
+                        }
+                        results.lock().unwrap_or_else(|e| e.into_inner())[idx] = Some(result);
+                        if let Some(ref func) = self.progress {
+                            func(&progress);
+                        }
+                    }
+                });
+            }
+        });
+        // Pass on a panic from a worker as if it happened here
+        if let Err(e) = scoped {
+            panic::resume_unwind(e);
+        }
+
+        let mut bulk = BulkResult {
+            results: Vec::new(),
+            skipped: Vec::new(),
+        };
+        let results = results.into_inner().unwrap_or_else(|e| e.into_inner());
+        for (item, result) in items.iter().zip(results) {
+            match result {
+                Some(result) => bulk.results.push((key(item), result)),
+                None => bulk.skipped.push(key(item)),
+            }
+        }
+        bulk
+    }
+}
+
+/// Whether the request may succeed if it is sent again. Only failures to reach the server (e.g.,
+/// through Tor) are, not local errors or errors from the server itself.
+fn is_transient(error: &Error) -> bool {
+    match error.status() {
+        Some(502) | Some(503) | Some(504) => true,
+        Some(_) => false,
+        None => *error.kind() == ErrorKind::NetworkError,
+    }
+}
+
+/// The result of a delete on attempt `attempt`. A retried delete that finds nothing means an
+/// earlier attempt went through.
+fn deleted(attempt: u32, result: Result<Response>) -> Result<Response> {
+    match result {
+        Err(ref e) if attempt > 0 && e.status() == Some(404) => {
+            Ok(Response::new("Already deleted".into()))
+        }
+        result => result,
+    }
+}
@@ -0,0 +1,321 @@
+//! Cache API responses and revalidate them with conditional requests.
+//!
+//! A `CachingTransport` wraps another `Transport`. When a `GET` returns JSON along with an `ETag`
+//! or `Last-Modified` header, the response is saved in a `CacheStore`. Later requests for the
+//! same URL send `If-None-Match` / `If-Modified-Since`, and if the server answers `304 Not
+//! Modified` the saved response is returned instead, so polling `Client::sources` only transfers
+//! the full list when it has changed. File downloads are never cached.
+//!
+//! The cache is best effort: if the store cannot be read or written, requests are sent as if
+//! nothing was cached. `DiskCache` stores source metadata unencrypted (readable only by the
+//! current user), so keep it on encrypted storage.
+//!
+//! The cache never learns that something was deleted on the server: a deleted source's metadata
+//! stays in the cache until its entry expires (see `DiskCache::max_age`) or is replaced by a
+//! newer response for the same URL. Call `CacheStore::clear` after deleting sources or when a
+//! journalist logs out.
+//!
+//! ```no_run
+//! # extern crate securedrop_api;
+//! use securedrop_api::auth::UserPassTotp;
+//! use securedrop_api::cache::{CachingTransport, DiskCache};
+//! use securedrop_api::transport::ReqwestTransport;
+//! use securedrop_api::Client;
+//!
+//! # fn main() {
+//! # let creds = UserPassTotp::new("journalist".into(), "pass".into(), "123456".into());
+//! let cache = DiskCache::open("cache").unwrap();
+//! let transport = CachingTransport::new(ReqwestTransport::new(), cache);
+//! let url = "http://localhost:8081".parse().unwrap();
+//! let client = Client::with_transport(url, transport, creds, None).unwrap();
+//! client.sources().unwrap();
+//! // Only transfers the list if it changed
+//! client.sources().unwrap();
+//! # }
+//! ```
+
+use json;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fmt::Write as FmtWrite;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use super::Result;
+use auth::{create_private_dir, write_private};
+use error::{Error, ErrorKind};
+use transport::{Method, Request, Response, Transport};
+
+/// A saved response and the validators needed to check whether it is still current.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl CacheEntry {
+    /// The URL of the request.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The response's `ETag` header.
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_ref().map(String::as_str)
+    }
+
+    /// The response's `Last-Modified` header.
+    pub fn last_modified(&self) -> Option<&str> {
+        self.last_modified.as_ref().map(String::as_str)
+    }
+
+    /// The response body.
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+}
+
+/// Storage for cached responses, keyed by URL.
+pub trait CacheStore: Send + Sync {
+    /// Look up the entry for a URL.
+    fn get(&self, url: &str) -> Result<Option<CacheEntry>>;
+
+    /// Save an entry, replacing any existing entry for the same URL.
+    fn put(&self, entry: CacheEntry) -> Result<()>;
+
+    /// Remove every entry.
+    fn clear(&self) -> Result<()>;
+}
+
+/// How long `DiskCache` keeps an entry unless `DiskCache::max_age` is set.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A `CacheStore` that keeps entries in memory.
+#[derive(Debug, Default)]
+pub struct MemoryCache {
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl MemoryCache {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CacheStore for MemoryCache {
+    fn get(&self, url: &str) -> Result<Option<CacheEntry>> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(entries.get(url).cloned())
+    }
+
+    fn put(&self, entry: CacheEntry) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.insert(entry.url.clone(), entry);
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        Ok(())
+    }
+}
+
+/// A `CacheStore` that keeps one JSON file per URL in a directory, readable only by the current
+/// user.
+///
+/// Entries expire `max_age` after they were saved (`DEFAULT_MAX_AGE` unless set). An expired
+/// entry is deleted when it is next looked up, and `DiskCache::remove_expired` deletes all of
+/// them.
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+    max_age: Duration,
+}
+
+impl DiskCache {
+    /// Open (or create) a cache in `dir`.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        create_private_dir(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            max_age: DEFAULT_MAX_AGE,
+        })
+    }
+
+    /// Set how long entries are kept.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Delete every expired entry.
+    pub fn remove_expired(&self) -> Result<()> {
+        for path in self.entry_paths()? {
+            if self.is_expired(&path)? {
+                remove_entry(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_expired(&self, path: &Path) -> Result<bool> {
+        let modified = fs::metadata(path)
+            .and_then(|m| m.modified())
+            .map_err(io_error)?;
+        // An entry from the future (e.g., after the clock changed) is not trusted either
+        Ok(match SystemTime::now().duration_since(modified) {
+            Ok(age) => age > self.max_age,
+            Err(_) => true,
+        })
+    }
+
+    fn entry_paths(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&self.dir).map_err(io_error)? {
+            let path = entry.map_err(io_error)?.path();
+            if path.extension().map_or(false, |e| e == "json") {
+                paths.push(path);
+            }
+        }
+        Ok(paths)
+    }
+
+    fn path(&self, url: &str) -> PathBuf {
+        let mut name = String::with_capacity(45);
+        for b in Sha1::digest(url.as_bytes()) {
+            let _ = write!(name, "{:02x}", b);
+        }
+        name.push_str(".json");
+        self.dir.join(name)
+    }
+}
+
+impl CacheStore for DiskCache {
+    fn get(&self, url: &str) -> Result<Option<CacheEntry>> {
+        let path = self.path(url);
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(io_error(e)),
+        };
+        if self.is_expired(&path)? {
+            remove_entry(&path)?;
+            return Ok(None);
+        }
+        let entry: CacheEntry = json::from_reader(BufReader::new(file))
+            .map_err(|e| ErrorKind::IO(format!("Invalid cache entry: {}", e)))?;
+        // Guard against hash collisions
+        Ok(Some(entry).filter(|e| e.url == url))
+    }
+
+    fn put(&self, entry: CacheEntry) -> Result<()> {
+        let bytes = json::to_vec(&entry).map_err(|e| ErrorKind::ProgrammingError(e.to_string()))?;
+        write_private(&self.path(&entry.url), &bytes)
+    }
+
+    fn clear(&self) -> Result<()> {
+        for path in self.entry_paths()? {
+            remove_entry(&path)?;
+        }
+        Ok(())
+    }
+}
+
+fn remove_entry(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        // Another process may have removed it first
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(io_error(e)),
+    }
+}
+
+fn io_error(e: io::Error) -> Error {
+    ErrorKind::IO(e.to_string()).into()
+}
+
+/// A `Transport` that caches `GET` responses and revalidates them with conditional requests.
+#[derive(Debug)]
+pub struct CachingTransport<T, C> {
+    inner: T,
+    store: C,
+}
+
+impl<T: Transport, C: CacheStore> CachingTransport<T, C> {
+    /// Cache responses from `inner` in `store`.
+    pub fn new(inner: T, store: C) -> Self {
+        Self { inner, store }
+    }
+
+    /// The cache.
+    pub fn store(&self) -> &C {
+        &self.store
+    }
+}
+
+impl<T: Transport, C: CacheStore> Transport for CachingTransport<T, C> {
+    fn send(&self, request: &Request) -> Result<Response> {
+        if request.method() != Method::Get {
+            return self.inner.send(request);
+        }
+
+        let url = request.url().as_str();
+        let cached = self.store.get(url).unwrap_or(None);
+        let response = match cached {
+            Some(ref entry) => {
+                let mut request = request.clone();
+                if let Some(ref etag) = entry.etag {
+                    request.set_header("If-None-Match", etag.clone());
+                }
+                if let Some(ref modified) = entry.last_modified {
+                    request.set_header("If-Modified-Since", modified.clone());
+                }
+                self.inner.send(&request)?
+            }
+            None => self.inner.send(request)?,
+        };
+
+        match (response.status(), cached) {
+            (304, Some(entry)) => Ok(Response::from_bytes(
+                200,
+                entry.headers,
+                entry.body.into_bytes(),
+            )),
+            (200, _) if is_cacheable(&response) => {
+                let etag = response.header("ETag").map(String::from);
+                let last_modified = response.header("Last-Modified").map(String::from);
+                let headers = response.headers().to_vec();
+                let body = response.read_body()?;
+                if let Ok(text) = String::from_utf8(body.clone()) {
+                    let entry = CacheEntry {
+                        url: url.to_string(),
+                        etag,
+                        last_modified,
+                        headers: headers.clone(),
+                        body: text,
+                    };
+                    let _ = self.store.put(entry);
+                }
+                Ok(Response::from_bytes(200, headers, body))
+            }
+            _ => Ok(response),
+        }
+    }
+}
+
+fn is_cacheable(response: &Response) -> bool {
+    let is_json = response
+        .header("Content-Type")
+        .map_or(false, |t| t.contains("json"));
+    is_json && (response.header("ETag").is_some() || response.header("Last-Modified").is_some())
+}
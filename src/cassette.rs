@@ -0,0 +1,458 @@
+//! Record and replay HTTP interactions for deterministic tests.
+//!
+//! A `Recorder` wraps another `Transport` and captures every request / response pair into a
+//! `Cassette` with credentials and tokens scrubbed. Bodies are recorded byte for byte except for
+//! the values of known secret fields in JSON bodies. A `Cassette` can be saved to disk as JSON and
+//! later loaded into a `Replayer`, which serves the recorded responses without touching the
+//! network.
+//!
+//! ```no_run
+//! # extern crate securedrop_api;
+//! use securedrop_api::auth::UserPassTotp;
+//! use securedrop_api::cassette::{Cassette, Recorder, Replayer};
+//! use securedrop_api::transport::ReqwestTransport;
+//! use securedrop_api::Client;
+//! use std::sync::Arc;
+//!
+//! # fn main() {
+//! # let creds = || UserPassTotp::new("journalist".into(), "pass".into(), "123456".into());
+//! // Against a staging server
+//! let recorder = Arc::new(Recorder::new(ReqwestTransport::new()));
+//! let url = "https://staging.example.org".parse().unwrap();
+//! let client = Client::with_transport(url, recorder.clone(), creds(), None).unwrap();
+//! client.sources().unwrap();
+//! recorder.cassette().save("tests/cassettes/sources.json").unwrap();
+//!
+//! // In a test
+//! let cassette = Cassette::load("tests/cassettes/sources.json").unwrap();
+//! let url = "https://staging.example.org".parse().unwrap();
+//! let client = Client::with_transport(url, Replayer::new(cassette), creds(), None).unwrap();
+//! client.sources().unwrap();
+//! # }
+//! ```
+
+use json::{self, Value};
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::Mutex;
+
+use super::Result;
+use error::{Error, ErrorKind};
+use transport::{Method, Request, Response, Transport};
+
+/// The value that replaces scrubbed secrets.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// JSON fields whose values are always scrubbed from recorded JSON bodies.
+const SECRET_FIELDS: &[&str] = &["password", "passphrase", "one_time_code", "token"];
+
+/// Headers that are recorded. All others (notably `Authorization` and `Set-Cookie`) are dropped.
+const RECORDED_HEADERS: &[&str] = &["Content-Type", "ETag", "Last-Modified"];
+
+/// A recorded HTTP body. Bodies that are valid UTF-8 are stored as text, and all others are stored
+/// as hex.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Body {
+    /// A UTF-8 body.
+    Text(String),
+    /// A binary body encoded as lowercase hex.
+    Hex(String),
+}
+
+impl Body {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        match ::std::str::from_utf8(bytes) {
+            Ok(s) => Body::Text(s.to_string()),
+            Err(_) => {
+                let mut hex = String::with_capacity(bytes.len() * 2);
+                for b in bytes {
+                    let _ = write!(hex, "{:02x}", b);
+                }
+                Body::Hex(hex)
+            }
+        }
+    }
+
+    fn to_bytes(&self) -> Result<Vec<u8>> {
+        match *self {
+            Body::Text(ref s) => Ok(s.clone().into_bytes()),
+            Body::Hex(ref s) => {
+                let err = || Error::new(ErrorKind::IO("Invalid hex in cassette body".into()));
+                if s.len() % 2 != 0 {
+                    return Err(err());
+                }
+                (0..s.len())
+                    .step_by(2)
+                    .map(|i| {
+                        s.get(i..i + 2)
+                            .and_then(|b| u8::from_str_radix(b, 16).ok())
+                            .ok_or_else(err)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// A request as it was recorded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    method: Method,
+    path: String,
+    body: Option<Body>,
+}
+
+impl RecordedRequest {
+    /// The HTTP method.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// The URL path and query string (e.g., `/api/v1/sources`).
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn matches(&self, request: &Request) -> bool {
+        self.method == request.method() && self.path == path_and_query(request)
+    }
+}
+
+/// A response as it was recorded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Body,
+}
+
+impl RecordedResponse {
+    /// The HTTP status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+}
+
+/// One request / response pair.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+impl Interaction {
+    /// The recorded request.
+    pub fn request(&self) -> &RecordedRequest {
+        &self.request
+    }
+
+    /// The recorded response.
+    pub fn response(&self) -> &RecordedResponse {
+        &self.response
+    }
+}
+
+/// An ordered list of recorded interactions.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    /// Create an empty cassette.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All recorded interactions in the order they occurred.
+    pub fn interactions(&self) -> &[Interaction] {
+        &self.interactions
+    }
+
+    /// Load a cassette from a JSON file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path).map_err(|e| ErrorKind::IO(e.to_string()))?;
+        json::from_reader(BufReader::new(file))
+            .map_err(|e| ErrorKind::IO(format!("Invalid cassette: {}", e)).into())
+    }
+
+    /// Save the cassette as a JSON file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let file = File::create(path).map_err(|e| ErrorKind::IO(e.to_string()))?;
+        json::to_writer_pretty(BufWriter::new(file), self)
+            .map_err(|e| ErrorKind::IO(e.to_string()).into())
+    }
+
+    fn record(
+        &mut self,
+        request: &Request,
+        status: u16,
+        headers: &[(String, String)],
+        body: &[u8],
+    ) {
+        let response_is_json = headers
+            .iter()
+            .any(|&(ref name, ref value)| is_json_content_type(name, value));
+        let headers = headers
+            .iter()
+            .filter(|&&(ref name, _)| {
+                RECORDED_HEADERS
+                    .iter()
+                    .any(|h| h.eq_ignore_ascii_case(name))
+            })
+            .cloned()
+            .collect();
+        self.interactions.push(Interaction {
+            request: RecordedRequest {
+                method: request.method(),
+                path: path_and_query(request),
+                body: request.body().map(|body| {
+                    let is_json = request
+                        .header("Content-Type")
+                        .map_or(false, |value| is_json_content_type("Content-Type", value));
+                    scrub_body(body, is_json)
+                }),
+            },
+            response: RecordedResponse {
+                status,
+                headers,
+                body: scrub_body(body, response_is_json),
+            },
+        });
+    }
+}
+
+fn path_and_query(request: &Request) -> String {
+    let url = request.url();
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    }
+}
+
+fn is_json_content_type(name: &str, value: &str) -> bool {
+    name.eq_ignore_ascii_case("Content-Type") && value.contains("json")
+}
+
+/// Record a body, replacing the values of secret fields if it is JSON. Everything else is kept
+/// byte for byte so that replayed responses are identical to the recorded ones.
+fn scrub_body(body: &[u8], is_json: bool) -> Body {
+    let mut value = match json::from_slice::<Value>(body) {
+        Ok(ref value) if is_json && !has_secrets(value) => return Body::from_bytes(body),
+        Ok(value) if is_json => value,
+        _ => return Body::from_bytes(body),
+    };
+
+    if let Some(text) = replace_secrets(body, &value) {
+        return Body::Text(text);
+    }
+    // The secrets could not be replaced in place, so fall back to reformatting the body
+    scrub_value(&mut value);
+    Body::Text(value.to_string())
+}
+
+/// Replace secret strings in the raw text of a JSON body so the rest keeps its formatting.
+fn replace_secrets(body: &[u8], value: &Value) -> Option<String> {
+    let mut secrets = Vec::new();
+    collect_secrets(value, &mut secrets);
+    if !secrets.iter().all(|s| s.is_string()) {
+        return None;
+    }
+
+    let mut text = String::from_utf8(body.to_vec()).ok()?;
+    let redacted = Value::String(REDACTED.into()).to_string();
+    for secret in secrets {
+        text = text.replace(&secret.to_string(), &redacted);
+    }
+    // Check nothing was missed (e.g., because the server escaped a secret differently)
+    match json::from_str::<Value>(&text) {
+        Ok(ref scrubbed) if !has_secrets(scrubbed) => Some(text),
+        _ => None,
+    }
+}
+
+fn has_secrets(value: &Value) -> bool {
+    let mut secrets = Vec::new();
+    collect_secrets(value, &mut secrets);
+    !secrets.is_empty()
+}
+
+/// Collect the values of secret fields that have not already been redacted.
+fn collect_secrets<'a>(value: &'a Value, secrets: &mut Vec<&'a Value>) {
+    match *value {
+        Value::Object(ref map) => {
+            for (key, value) in map.iter() {
+                if !SECRET_FIELDS.contains(&key.as_str()) {
+                    collect_secrets(value, secrets);
+                } else if value.as_str() != Some(REDACTED) {
+                    secrets.push(value);
+                }
+            }
+        }
+        Value::Array(ref values) => {
+            for value in values {
+                collect_secrets(value, secrets);
+            }
+        }
+        _ => (),
+    }
+}
+
+fn scrub_value(value: &mut Value) {
+    match *value {
+        Value::Object(ref mut map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_FIELDS.contains(&key.as_str()) {
+                    *value = Value::String(REDACTED.into());
+                } else {
+                    scrub_value(value);
+                }
+            }
+        }
+        Value::Array(ref mut values) => {
+            for value in values.iter_mut() {
+                scrub_value(value);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// A `Transport` that passes requests to another transport and records the interactions. Response
+/// bodies are read fully into memory so they can be recorded.
+pub struct Recorder<T> {
+    inner: T,
+    cassette: Mutex<Cassette>,
+}
+
+impl<T: Transport> Recorder<T> {
+    /// Record all interactions sent through `inner`.
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            cassette: Mutex::new(Cassette::new()),
+        }
+    }
+
+    /// A copy of everything recorded so far.
+    pub fn cassette(&self) -> Cassette {
+        self.cassette
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl<T: Transport> Transport for Recorder<T> {
+    fn send(&self, request: &Request) -> Result<Response> {
+        let response = self.inner.send(request)?;
+        let status = response.status();
+        let headers = response.headers().to_vec();
+        let body = response.read_body()?;
+        self.cassette
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .record(request, status, &headers, &body);
+        Ok(Response::from_bytes(status, headers, body))
+    }
+}
+
+/// A `Transport` that serves responses from a `Cassette`.
+///
+/// Each request is answered by the first unused interaction with the same method and path, so
+/// requests for different resources may arrive in a different order than they were recorded.
+/// Requests with no matching interaction return `Err`.
+pub struct Replayer {
+    remaining: Mutex<Vec<Interaction>>,
+}
+
+impl Replayer {
+    /// Replay the interactions in the cassette.
+    pub fn new(cassette: Cassette) -> Self {
+        Self {
+            remaining: Mutex::new(cassette.interactions),
+        }
+    }
+
+    /// The number of interactions that have not yet been replayed.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .len()
+    }
+}
+
+impl Transport for Replayer {
+    fn send(&self, request: &Request) -> Result<Response> {
+        let mut remaining = self.remaining.lock().unwrap_or_else(|e| e.into_inner());
+        let idx = remaining
+            .iter()
+            .position(|i| i.request.matches(request))
+            .ok_or_else(|| {
+                ErrorKind::IO(format!(
+                    "No recorded interaction for {} {}",
+                    request.method(),
+                    path_and_query(request)
+                ))
+            })?;
+        let response = remaining.remove(idx).response;
+        Ok(Response::from_bytes(
+            response.status,
+            response.headers,
+            response.body.to_bytes()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scrub_secrets() {
+        let body =
+            br#"{"username": "journalist", "password": "hunter2", "one_time_code": "123456"}"#;
+        assert_eq!(
+            scrub_body(body, true),
+            Body::Text(
+                r#"{"username": "journalist", "password": "[REDACTED]", "one_time_code": "[REDACTED]"}"#
+                    .into()
+            )
+        );
+
+        // Values that are not strings are not replaced in place
+        let body = br#"{ "token": 12345, "expires": "2018-10-09T21:40:12Z" }"#;
+        assert_eq!(
+            scrub_body(body, true),
+            Body::Text(r#"{"expires":"2018-10-09T21:40:12Z","token":"[REDACTED]"}"#.into())
+        );
+    }
+
+    #[test]
+    fn bodies_are_recorded_verbatim() {
+        let body = b"{ \"sources\": [],\n  \"count\": 0 }";
+        assert_eq!(
+            scrub_body(body, true),
+            Body::Text(String::from_utf8(body.to_vec()).unwrap())
+        );
+
+        // A downloaded file is never scrubbed, even if it happens to be JSON
+        let body = br#"{"token": "not a secret"}"#;
+        assert_eq!(
+            scrub_body(body, false),
+            Body::Text(String::from_utf8(body.to_vec()).unwrap())
+        );
+    }
+
+    #[test]
+    fn binary_bodies_round_trip() {
+        let bytes = vec![0x00, 0xff, 0x85, 0x0a];
+        let body = Body::from_bytes(&bytes);
+        assert_eq!(body, Body::Hex("00ff850a".into()));
+        assert_eq!(body.to_bytes().unwrap(), bytes);
+    }
+}
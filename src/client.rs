@@ -1,23 +1,32 @@
 //! API client.
 
-use reqwest::header::{Accept, Authorization as AuthHeader, ContentType, Headers, UserAgent};
-use reqwest::{self, Client as HttpClient, Response as HttpResponse, Url};
+use json;
+use reqwest::Url;
 use serde::de::DeserializeOwned;
-use std::io::Write;
-use uuid::Uuid;
+use serde::ser::Serialize;
+use std::io::{self, Write};
+use std::time::Duration;
+use zeroize::Zeroizing;
 
 use super::Result;
-use auth::{Authorization, Credentials};
-use data::{Reply, Response, Source, Sources, Submission, Submissions, User};
-use error::{Error, ErrorKind};
+use auth::{AuthToken, Authorization, Credentials};
+use conversation::Conversation;
+use data::{
+    Replies, Reply, ReplyCreated, ReplyId, Response, Seen, SentReply, Source, SourceId, Sources,
+    Submission, SubmissionId, Submissions, User, Users,
+};
+use error::{Error, ErrorKind, ErrorResponse};
+use page::Pages;
+use transport::{Method, Request, ReqwestTransport, Response as HttpResponse, Transport};
 
 /// A client used to interact with the SecureDrop API. This client handles authentication and
 /// retries.
 pub struct Client {
     url_base: Url,
-    http: HttpClient,
+    transport: Box<dyn Transport>,
     user_agent: String,
     auth: Authorization,
+    timeout: Option<Duration>,
 }
 
 impl Client {
@@ -30,18 +39,79 @@ impl Client {
     where
         C: Into<Credentials>,
     {
+        Self::with_transport(url_base, ReqwestTransport::new(), credentials, user_agent)
+    }
+
+    /// Construct a new `Client` that sends all requests using the given `Transport`. See
+    /// `Client::new` for details.
+    pub fn with_transport<T, C>(
+        url_base: Url,
+        transport: T,
+        credentials: C,
+        user_agent: Option<String>,
+    ) -> Result<Self>
+    where
+        T: Transport + 'static,
+        C: Into<Credentials>,
+    {
+        let auth = Authorization::Credentials(credentials.into());
+        let mut client = Self::build(url_base, Box::new(transport), auth, user_agent);
+        client.authorize()?;
+        Ok(client)
+    }
+
+    /// Construct a new `Client` from a previously issued token (see `Client::auth_token`) instead
+    /// of logging in. No request is made, so an invalid token is only detected on first use.
+    ///
+    /// This will return an `Err` if the token has already expired.
+    pub fn from_token(url_base: Url, token: AuthToken, user_agent: Option<String>) -> Result<Self> {
+        Self::from_token_with_transport(url_base, ReqwestTransport::new(), token, user_agent)
+    }
+
+    /// Construct a new `Client` from a token that sends all requests using the given `Transport`.
+    /// See `Client::from_token` for details.
+    pub fn from_token_with_transport<T>(
+        url_base: Url,
+        transport: T,
+        token: AuthToken,
+        user_agent: Option<String>,
+    ) -> Result<Self>
+    where
+        T: Transport + 'static,
+    {
+        if token.is_expired() {
+            return Err(ErrorKind::AuthError.into());
+        }
+        let auth = Authorization::Token(token);
+        Ok(Self::build(url_base, Box::new(transport), auth, user_agent))
+    }
+
+    fn build(
+        url_base: Url,
+        transport: Box<dyn Transport>,
+        auth: Authorization,
+        user_agent: Option<String>,
+    ) -> Self {
         let user_agent = match user_agent {
             Some(ua) => format!("{} (rust-securedrop-api/{})", ua, env!("CARGO_PKG_VERSION")),
             None => format!("rust-securedrop-api/{}", env!("CARGO_PKG_VERSION")),
         };
-        let mut client = Self {
+        Self {
             url_base: url_base,
-            http: HttpClient::new(),
+            transport: transport,
             user_agent: user_agent,
-            auth: Authorization::Credentials(credentials.into()),
-        };
-        client.authorize()?;
-        Ok(client)
+            auth: auth,
+            timeout: None,
+        }
+    }
+
+    /// The token the client is currently using, if it is logged in. Save this (e.g., with a
+    /// `TokenStore`) to construct a client later with `Client::from_token`.
+    pub fn auth_token(&self) -> Option<&AuthToken> {
+        match self.auth {
+            Authorization::Token(ref token) => Some(token),
+            Authorization::Credentials(_) => None,
+        }
     }
 
     fn url(&self, path: &str) -> Url {
@@ -50,20 +120,55 @@ impl Client {
         url
     }
 
-    fn headers(&self) -> Headers {
-        let mut headers = Headers::new();
-        headers.set(ContentType::json());
-        headers.set(Accept::json());
-        headers.set(UserAgent::new(self.user_agent.clone()));
-        self.auth_header(&mut headers);
-        headers
+    /// Set how long to wait for each response. `None` (the default) uses the transport's default.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    fn request(&self, method: Method, path: &str) -> Request {
+        self.request_url(method, self.url(path))
     }
 
-    fn auth_header(&self, headers: &mut Headers) {
+    fn request_url(&self, method: Method, url: Url) -> Request {
+        let mut req = Request::new(method, url);
+        req.set_timeout(self.timeout);
+        req.set_header("Content-Type", "application/json");
+        req.set_header("Accept", "application/json");
+        req.set_header("User-Agent", self.user_agent.clone());
         match self.auth {
-            Authorization::Token(ref token) => headers.set(AuthHeader(format!("Token {}", token))),
+            Authorization::Token(ref token) => {
+                let value = Zeroizing::new(format!("Token {}", token.token().expose_secret()));
+                req.set_header("Authorization", value.as_str())
+            }
             Authorization::Credentials(_) => (),
         }
+        req
+    }
+
+    fn json_request<B>(&self, method: Method, path: &str, body: &B) -> Result<Request>
+    where
+        B: Serialize,
+    {
+        let mut req = self.request(method, path);
+        // The login body holds the passphrase, and the request wipes its own copy when dropped
+        let body = json::to_vec(body).map_err(|e| ErrorKind::ProgrammingError(e.to_string()))?;
+        let body = Zeroizing::new(body);
+        req.set_body(body.to_vec());
+        Ok(req)
+    }
+
+    /// Send a `GET` with the given query parameters, returning the status along with the parsed
+    /// body.
+    pub(crate) fn get_with_query<T>(&self, path: &str, query: &[(&str, String)]) -> Result<(u16, T)>
+    where
+        T: DeserializeOwned,
+    {
+        let mut url = self.url(path);
+        url.query_pairs_mut()
+            .extend_pairs(query.iter().map(|&(k, ref v)| (k, v)));
+        let resp = self.transport.send(&self.request_url(Method::Get, url))?;
+        let status = resp.status();
+        Self::parse_json(Ok(resp)).map(|body| (status, body))
     }
 
     /// Reauthorize the client using a new set of credentials. This may need to be done if a client
@@ -77,213 +182,296 @@ impl Client {
         self.authorize()
     }
 
+    /// Reauthorize like `Client::reauthorize`, but if the server rejects the one time code of an
+    /// `HotpSecret`, retry with the following counters up to its resync window (see
+    /// `HotpSecret::set_resync_window`). Only use this when the passphrase is known to be good,
+    /// since every retry after a wrong passphrase burns a code and a failed login.
+    pub fn reauthorize_with_resync<C>(&mut self, credentials: C) -> Result<()>
+    where
+        C: Into<Credentials>,
+    {
+        let credentials = credentials.into();
+        let retries = credentials.resync_attempts();
+        self.auth = Authorization::Credentials(credentials);
+        let mut result = self.authorize();
+        for _ in 0..retries {
+            // Only a rejected login (4xx) is worth retrying with a fresh code
+            if let Err(ref e) = result {
+                if let ErrorKind::ClientError(_) = *e.kind() {
+                    result = self.authorize();
+                    continue;
+                }
+            }
+            break;
+        }
+        result
+    }
+
     fn authorize(&mut self) -> Result<()> {
-        let url = self.url("token");
-        let headers = self.headers();
-        let resp = match self.auth {
+        let req = match self.auth {
             Authorization::Credentials(ref creds) => {
-                self.http.post(url).headers(headers).json(&creds).send()
+                self.json_request(Method::Post, "token", &creds.login_body()?)?
             }
-            Authorization::Token(_) => self.http.post(url).headers(headers).send(),
+            Authorization::Token(_) => self.request(Method::Post, "token"),
         };
-        let auth = Self::parse_json(resp)?;
-        self.auth = Authorization::Token(auth);
+        let token = Self::parse_json(self.transport.send(&req))?;
+        self.auth = Authorization::Token(token);
         Ok(())
     }
 
-    fn parse_json<T>(resp: ::std::result::Result<HttpResponse, reqwest::Error>) -> Result<T>
+    fn parse_json<T>(resp: Result<HttpResponse>) -> Result<T>
     where
         T: DeserializeOwned,
     {
-        Self::parse_req(resp, |resp| match resp.json::<T>() {
-            Ok(a) => Ok(a),
-            Err(e) => Err(ErrorKind::ProgrammingError(e.to_string()).into()),
+        Self::parse_req(resp, |mut resp| {
+            match json::from_reader::<_, T>(resp.body()) {
+                Ok(a) => Ok(a),
+                Err(e) => Err(ErrorKind::ProgrammingError(e.to_string()).into()),
+            }
         })
     }
 
-    fn parse_req<T, F>(
-        mut resp: ::std::result::Result<HttpResponse, reqwest::Error>,
-        func: F,
-    ) -> Result<T>
+    fn parse_req<T, F>(resp: Result<HttpResponse>, func: F) -> Result<T>
     where
-        F: FnOnce(&mut HttpResponse) -> Result<T>,
+        F: FnOnce(HttpResponse) -> Result<T>,
     {
-        match resp {
-            Ok(ref mut resp) if resp.status().is_success() => func(resp),
-            Ok(mut resp) => {
-                if resp.status().is_server_error() {
-                    Err(ErrorKind::ServerError.into())
-                } else if resp.status().is_client_error() {
-                    let err = match resp.json() {
-                        Ok(err) => err,
-                        Err(_) => {
-                            return Err(ErrorKind::ProgrammingError("Parse failure.".into()).into())
-                        }
-                    };
-                    Err(ErrorKind::ClientError(err).into())
-                } else {
-                    Err(ErrorKind::UnknownError.into())
-                }
-            }
-            Err(err) => {
-                if !err.is_http() {
-                    Err(ErrorKind::NetworkError.into())
-                } else if err.is_server_error() {
-                    Err(ErrorKind::ServerError.into())
-                } else {
-                    Err(ErrorKind::UnknownError.into())
-                }
-            }
-        }
+        let mut resp = resp?;
+        let status = resp.status();
+        let kind = match status {
+            200..=299 => return func(resp),
+            400..=499 => match json::from_reader::<_, ErrorResponse>(resp.body()) {
+                Ok(err) => ErrorKind::ClientError(err.to_string()),
+                Err(_) => ErrorKind::ProgrammingError("Parse failure.".into()),
+            },
+            500..=599 => ErrorKind::ServerError,
+            _ => ErrorKind::UnknownError,
+        };
+        Err(Error::new(kind).with_status(status))
     }
 
     /// Retrieve all sources the logged in user is permitted to view.
     ///
     /// Corresponds to `GET /api/v1/sources`.
     pub fn sources(&self) -> Result<Sources> {
-        let resp = self
-            .http
-            .get(self.url("sources"))
-            .headers(self.headers())
-            .send();
+        let resp = self.transport.send(&self.request(Method::Get, "sources"));
         Self::parse_json(resp)
     }
 
+    /// Retrieve sources `limit` at a time as they are iterated over. Servers that do not paginate
+    /// return every source in the first page.
+    ///
+    /// Corresponds to `GET /api/v1/sources?limit=<limit>`.
+    pub fn sources_paged(&self, limit: u32) -> Pages<Source> {
+        Pages::new(self, "sources".into(), "sources", limit)
+    }
+
     /// Retrieve one source by ID.
     ///
     /// Corresponds to `GET /api/v1/source/<uuid:uuid:>`.
-    pub fn source(&self, uuid: &Uuid) -> Result<Source> {
+    pub fn source(&self, uuid: &SourceId) -> Result<Source> {
         let resp = self
-            .http
-            .get(self.url(&format!("sources/{}", uuid)))
-            .headers(self.headers())
-            .send();
+            .transport
+            .send(&self.request(Method::Get, &format!("sources/{}", uuid)));
         Self::parse_json(resp)
     }
 
     /// Retrieve all submissions for a given source.
     ///
     /// Corresponds to `GET /api/v1/source/<uuid:uuid:>/submissions`.
-    pub fn source_submissions(&self, uuid: &Uuid) -> Result<Submissions> {
+    pub fn source_submissions(&self, uuid: &SourceId) -> Result<Submissions> {
         let resp = self
-            .http
-            .get(self.url(&format!("sources/{}/submissions", uuid)))
-            .headers(self.headers())
-            .send();
+            .transport
+            .send(&self.request(Method::Get, &format!("sources/{}/submissions", uuid)));
         Self::parse_json(resp)
     }
 
+    /// Retrieve submissions for a given source `limit` at a time as they are iterated over.
+    /// Servers that do not paginate return every submission in the first page.
+    ///
+    /// Corresponds to `GET /api/v1/source/<uuid:uuid:>/submissions?limit=<limit>`.
+    pub fn source_submissions_paged(&self, uuid: &SourceId, limit: u32) -> Pages<Submission> {
+        Pages::new(
+            self,
+            format!("sources/{}/submissions", uuid),
+            "submissions",
+            limit,
+        )
+    }
+
     /// Retrieve one submission from a given source.
     ///
-    /// Corresponds to `GET /api/v1/soruces/<uuid:uuid:>/submissions/<int:submission_id>`.
-    pub fn source_submission(&self, uuid: &Uuid, submission_id: u32) -> Result<Submission> {
-        let resp = self
-            .http
-            .get(self.url(&format!(
-                "sources/{}/submissions/{}",
-                uuid, submission_id
-            )))
-            .headers(self.headers())
-            .send();
+    /// Corresponds to `GET /api/v1/soruces/<uuid:uuid:>/submissions/<uuid:submission_uuid>`.
+    pub fn source_submission(
+        &self,
+        uuid: &SourceId,
+        submission_id: &SubmissionId,
+    ) -> Result<Submission> {
+        let resp = self.transport.send(&self.request(
+            Method::Get,
+            &format!("sources/{}/submissions/{}", uuid, submission_id),
+        ));
         Self::parse_json(resp)
     }
 
     /// Send a pre-encrypted reply to the given source.
     ///
+    /// If this fails without a response from the server, use `reply_exists` to check whether the
+    /// reply was stored before sending it again.
+    ///
     /// Corresponds to `POST /api/v1/sources/<uuid:uuid:>/reply`.
-    pub fn reply_to_source(&self, uuid: &Uuid, reply: &Reply) -> Result<Response> {
-        let resp = self
-            .http
-            .post(self.url(&format!("sources/{}/reply", uuid)))
-            .headers(self.headers())
-            .json(&reply)
-            .send();
+    pub fn reply_to_source(&self, uuid: &SourceId, reply: &Reply) -> Result<ReplyCreated> {
+        let req = self.json_request(Method::Post, &format!("sources/{}/reply", uuid), reply)?;
+        let resp = self.transport.send(&req);
         Self::parse_json(resp)
     }
 
+    /// Check whether a reply with the given UUID has been stored for a source. Returns `false` if
+    /// the source does not exist.
+    ///
+    /// Corresponds to `GET /api/v1/sources/<uuid:uuid:>/replies/<uuid:reply_uuid>`.
+    pub fn reply_exists(&self, uuid: &SourceId, reply_uuid: &ReplyId) -> Result<bool> {
+        let resp = self.transport.send(&self.request(
+            Method::Get,
+            &format!("sources/{}/replies/{}", uuid, reply_uuid),
+        ));
+        match resp {
+            Ok(ref resp) if resp.status() == 404 => Ok(false),
+            resp => Self::parse_json::<SentReply>(resp).map(|_| true),
+        }
+    }
+
     /// Delete one submission for a given source.
     ///
-    /// Corresponds to `DELETE /api/v1/sources/<uuid:uuid:>/submissions/<int:submission_id>`.
+    /// Corresponds to `DELETE /api/v1/sources/<uuid:uuid:>/submissions/<uuid:submission_uuid>`.
     pub fn delete_source_submission(
         &self,
-        uuid: &Uuid,
-        submission_id: u32,
-    ) -> Result<Submission> {
-        let resp = self
-            .http
-            .delete(self.url(&format!(
-                "sources/{}/submissions/{}",
-                uuid, submission_id
-            )))
-            .headers(self.headers())
-            .send();
+        uuid: &SourceId,
+        submission_id: &SubmissionId,
+    ) -> Result<Response> {
+        let resp = self.transport.send(&self.request(
+            Method::Delete,
+            &format!("sources/{}/submissions/{}", uuid, submission_id),
+        ));
         Self::parse_json(resp)
     }
 
     /// Download one submission to a sink (`Write`).
     ///
     /// Corresponds to `GET
-    /// /api/v1/sources/<uuid:uuid:>/submissions/<int:submission_id>/download`.
+    /// /api/v1/sources/<uuid:uuid:>/submissions/<uuid:submission_uuid>/download`.
     pub fn download_submission<W>(
         &self,
-        uuid: &Uuid,
-        submission_id: u32,
+        uuid: &SourceId,
+        submission_id: &SubmissionId,
         mut write: W,
     ) -> Result<()>
     where
         W: Write,
     {
-        let mut headers = Headers::new();
-        headers.set(ContentType("appication/pgp-encrypted".parse().unwrap()));
-        self.auth_header(&mut headers);
+        let mut req = self.request(
+            Method::Get,
+            &format!("sources/{}/submissions/{}/download", uuid, submission_id),
+        );
+        req.set_header("Content-Type", "application/pgp-encrypted");
+        let resp = self.transport.send(&req);
+        Self::parse_req(resp, move |resp| {
+            io::copy(&mut resp.into_body(), &mut write)
+                .map_err(|e| Error::new(ErrorKind::IO(format!("{:?}", e))))?;
+            Ok(())
+        })
+    }
+
+    /// Retrieve all replies sent to a given source.
+    ///
+    /// Corresponds to `GET /api/v1/sources/<uuid:uuid:>/replies`.
+    pub fn source_replies(&self, uuid: &SourceId) -> Result<Replies> {
         let resp = self
-            .http
-            .get(self.url(&format!(
-                "sources/{}/submissions/{}/download",
-                uuid, submission_id
-            )))
-            .headers(headers)
-            .send();
+            .transport
+            .send(&self.request(Method::Get, &format!("sources/{}/replies", uuid)));
+        Self::parse_json(resp)
+    }
+
+    /// Retrieve one reply sent to a given source.
+    ///
+    /// Corresponds to `GET /api/v1/sources/<uuid:uuid:>/replies/<uuid:reply_uuid>`.
+    pub fn source_reply(&self, uuid: &SourceId, reply_uuid: &ReplyId) -> Result<SentReply> {
+        let resp = self.transport.send(&self.request(
+            Method::Get,
+            &format!("sources/{}/replies/{}", uuid, reply_uuid),
+        ));
+        Self::parse_json(resp)
+    }
+
+    /// Download one reply to a sink (`Write`).
+    ///
+    /// Corresponds to `GET /api/v1/sources/<uuid:uuid:>/replies/<uuid:reply_uuid>/download`.
+    pub fn download_reply<W>(
+        &self,
+        uuid: &SourceId,
+        reply_uuid: &ReplyId,
+        mut write: W,
+    ) -> Result<()>
+    where
+        W: Write,
+    {
+        let mut req = self.request(
+            Method::Get,
+            &format!("sources/{}/replies/{}/download", uuid, reply_uuid),
+        );
+        req.set_header("Content-Type", "application/pgp-encrypted");
+        let resp = self.transport.send(&req);
         Self::parse_req(resp, move |resp| {
-            resp.copy_to(&mut write)
+            io::copy(&mut resp.into_body(), &mut write)
                 .map_err(|e| Error::new(ErrorKind::IO(format!("{:?}", e))))?;
             Ok(())
         })
     }
 
+    /// Retrieve all submissions and replies for a given source and merge them into a single
+    /// chronological conversation.
+    pub fn conversation(&self, uuid: &SourceId) -> Result<Conversation> {
+        let submissions = self.source_submissions(uuid)?;
+        let replies = self.source_replies(uuid)?;
+        Ok(Conversation::new(submissions, replies))
+    }
+
     /// Delete a source and all submissions.
     ///
     /// Corresponds to `DELETE /api/v1/sources/<uuid:uuid:>/submissions>`.
-    pub fn delete_submissions(&self, uuid: &Uuid) -> Result<Response> {
+    pub fn delete_submissions(&self, uuid: &SourceId) -> Result<Response> {
         let resp = self
-            .http
-            .delete(self.url(&format!("sources/{}/submissions", uuid,)))
-            .headers(self.headers())
-            .send();
+            .transport
+            .send(&self.request(Method::Delete, &format!("sources/{}/submissions", uuid)));
         Self::parse_json(resp)
     }
 
     /// Add a star to a source.
     ///
     /// Corresponds to `POST /api/v1/soruces/<uuid:uuid:>/star`.
-    pub fn star_source(&self, uuid: &Uuid) -> Result<Response> {
+    pub fn star_source(&self, uuid: &SourceId) -> Result<Response> {
         let resp = self
-            .http
-            .post(self.url(&format!("sources/{}/star", uuid,)))
-            .headers(self.headers())
-            .send();
+            .transport
+            .send(&self.request(Method::Post, &format!("sources/{}/star", uuid)));
         Self::parse_json(resp)
     }
 
     /// Remove a star from a source.
     ///
     /// Corresponds to `DELETE /api/v1/soruces/<uuid:uuid:>/star`.
-    pub fn unstar_source(&self, uuid: &Uuid) -> Result<Response> {
+    pub fn unstar_source(&self, uuid: &SourceId) -> Result<Response> {
         let resp = self
-            .http
-            .delete(self.url(&format!("sources/{}/star", uuid,)))
-            .headers(self.headers())
-            .send();
+            .transport
+            .send(&self.request(Method::Delete, &format!("sources/{}/star", uuid)));
+        Self::parse_json(resp)
+    }
+
+    /// Mark submissions and replies as seen by the logged in user. Older servers do not support
+    /// this and return a `ClientError`.
+    ///
+    /// Corresponds to `POST /api/v1/seen`.
+    pub fn mark_seen(&self, seen: &Seen) -> Result<Response> {
+        let req = self.json_request(Method::Post, "seen", seen)?;
+        let resp = self.transport.send(&req);
         Self::parse_json(resp)
     }
 
@@ -291,11 +479,15 @@ impl Client {
     ///
     /// Corresponds to `GET /api/v1/user`.
     pub fn user(&self) -> Result<User> {
-        let resp = self
-            .http
-            .get(self.url("user"))
-            .headers(self.headers())
-            .send();
+        let resp = self.transport.send(&self.request(Method::Get, "user"));
+        Self::parse_json(resp)
+    }
+
+    /// Retrieve all journalists.
+    ///
+    /// Corresponds to `GET /api/v1/users`.
+    pub fn users(&self) -> Result<Users> {
+        let resp = self.transport.send(&self.request(Method::Get, "users"));
         Self::parse_json(resp)
     }
 }
@@ -0,0 +1,206 @@
+//! Chronological views of the messages, documents, and replies exchanged with a source.
+
+use std::slice::Iter;
+
+use data::{self, FileKind, FileName, JournalistId, Replies, SentReply, Submission, Submissions};
+
+/// The party who authored an item in a conversation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Author<'a> {
+    /// The source.
+    Source,
+    /// A journalist.
+    Journalist {
+        /// The journalist's username.
+        username: &'a str,
+        /// A unique identifier of the journalist.
+        uuid: &'a JournalistId,
+    },
+}
+
+/// One item in a conversation with a source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    /// A text message from the source.
+    Message(Submission),
+    /// A document uploaded by the source.
+    File(Submission),
+    /// A reply from a journalist to the source.
+    Reply(SentReply),
+}
+
+impl Item {
+    fn from_submission(submission: Submission) -> Self {
+        match submission.kind() {
+            Ok(FileKind::Message) => Item::Message(submission),
+            // Anything that isn't clearly a message is treated as an opaque file.
+            _ => Item::File(submission),
+        }
+    }
+
+    /// The SecureDrop filename of the item.
+    pub fn filename(&self) -> &str {
+        match *self {
+            Item::Message(ref s) | Item::File(ref s) => s.filename(),
+            Item::Reply(ref r) => r.filename(),
+        }
+    }
+
+    /// Parse the SecureDrop filename into its components. This returns `None` if the filename is
+    /// not in the expected format.
+    pub fn parsed_filename(&self) -> Option<FileName> {
+        self.filename().parse().ok()
+    }
+
+    /// The size of the item in bytes.
+    pub fn size(&self) -> u64 {
+        match *self {
+            Item::Message(ref s) | Item::File(ref s) => s.size(),
+            Item::Reply(ref r) => r.size(),
+        }
+    }
+
+    /// Flag for whether or not the item has been read. Replies are authored by journalists and
+    /// are always considered read.
+    pub fn is_read(&self) -> bool {
+        match *self {
+            Item::Message(ref s) | Item::File(ref s) => s.is_read(),
+            Item::Reply(_) => true,
+        }
+    }
+
+    /// Who authored the item.
+    pub fn author(&self) -> Author {
+        match *self {
+            Item::Message(_) | Item::File(_) => Author::Source,
+            Item::Reply(ref r) => Author::Journalist {
+                username: r.journalist_username(),
+                uuid: r.journalist_uuid(),
+            },
+        }
+    }
+}
+
+/// All messages, documents, and replies for one source in the order they were exchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conversation {
+    items: Vec<Item>,
+}
+
+impl Conversation {
+    /// Merge a source's submissions and replies into a single ordered conversation. Items whose
+    /// filenames cannot be parsed are placed at the end.
+    pub fn new(submissions: Submissions, replies: Replies) -> Self {
+        let mut items: Vec<Item> = submissions
+            .submissions()
+            .iter()
+            .cloned()
+            .map(Item::from_submission)
+            .chain(replies.replies().iter().cloned().map(Item::Reply))
+            .collect();
+        items.sort_by_cached_key(|i| data::chronological_key(i.filename()));
+        Self { items }
+    }
+
+    /// All items in the conversation, oldest first.
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    /// Iterate over the items in the conversation, oldest first.
+    pub fn iter(&self) -> Iter<Item> {
+        self.items.iter()
+    }
+
+    /// The number of items that have not yet been read.
+    pub fn unread_count(&self) -> usize {
+        self.items.iter().filter(|i| !i.is_read()).count()
+    }
+}
+
+impl<'a> IntoIterator for &'a Conversation {
+    type Item = &'a Item;
+    type IntoIter = Iter<'a, Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use json;
+
+    fn submission(filename: &str, is_read: bool) -> String {
+        format!(
+            r#"{{
+                "uuid": "2a6c0f5e-8a55-4a3f-b8a0-2a0c1b2f1f4d",
+                "source_uuid": "8c3b4d6e-1c4e-4d2a-9a0b-6b1f2f2c7e11",
+                "filename": "{}",
+                "is_read": {},
+                "size": 604
+            }}"#,
+            filename, is_read
+        )
+    }
+
+    fn reply(filename: &str) -> String {
+        format!(
+            r#"{{
+                "uuid": "5e3c8f0a-2d4b-4c6e-9f1a-3b5d7e9f1a2c",
+                "filename": "{}",
+                "is_deleted_by_source": false,
+                "journalist_username": "journalist",
+                "journalist_uuid": "b1c2d3e4-f5a6-4b7c-8d9e-0f1a2b3c4d5e",
+                "size": 1116
+            }}"#,
+            filename
+        )
+    }
+
+    #[test]
+    fn interleave_in_chronological_order() {
+        let submissions: Submissions = json::from_str(&format!(
+            r#"{{ "submissions": [{}, {}, {}] }}"#,
+            submission("10-uninteresting_agglutination-msg.gpg", false),
+            submission("not a securedrop filename", true),
+            submission("2-uninteresting_agglutination-doc.gz.gpg", true),
+        ))
+        .unwrap();
+        let replies: Replies = json::from_str(&format!(
+            r#"{{ "replies": [{}, {}] }}"#,
+            reply("3-uninteresting_agglutination-reply.gpg"),
+            reply("1-uninteresting_agglutination-reply.gpg"),
+        ))
+        .unwrap();
+
+        let conversation = Conversation::new(submissions, replies);
+        let filenames: Vec<&str> = conversation.iter().map(|i| i.filename()).collect();
+        assert_eq!(
+            filenames,
+            vec![
+                "1-uninteresting_agglutination-reply.gpg",
+                "2-uninteresting_agglutination-doc.gz.gpg",
+                "3-uninteresting_agglutination-reply.gpg",
+                "10-uninteresting_agglutination-msg.gpg",
+                "not a securedrop filename",
+            ]
+        );
+
+        let items = conversation.items();
+        assert_eq!(
+            items[0].author(),
+            Author::Journalist {
+                username: "journalist",
+                uuid: &"b1c2d3e4-f5a6-4b7c-8d9e-0f1a2b3c4d5e".parse().unwrap(),
+            }
+        );
+        assert_eq!(items[1].author(), Author::Source);
+        match (&items[1], &items[3], &items[4]) {
+            (&Item::File(_), &Item::Message(_), &Item::File(_)) => (),
+            _ => panic!("Unexpected item kinds: {:?}", items),
+        }
+        assert_eq!(conversation.unread_count(), 1);
+    }
+}
@@ -1,18 +1,90 @@
 //! API request / response data types.
 
 use chrono::{DateTime, Utc};
+use json::{Map, Value};
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::str::FromStr;
 use uuid::Uuid;
 
 use super::Result;
 use error::ErrorKind;
+use query::SourceQuery;
+
+macro_rules! id_type {
+    ($(#[$attr:meta])* $name:ident, $what:expr) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+        pub struct $name(Uuid);
+
+        impl $name {
+            /// Wrap a `Uuid` as an identifier.
+            pub fn new(uuid: Uuid) -> Self {
+                $name(uuid)
+            }
+
+            /// The underlying `Uuid`.
+            pub fn uuid(&self) -> &Uuid {
+                &self.0
+            }
+        }
+
+        impl From<Uuid> for $name {
+            fn from(uuid: Uuid) -> Self {
+                $name(uuid)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ::error::Error;
+
+            fn from_str(s: &str) -> Result<Self> {
+                s.parse().map($name).map_err(|_| {
+                    ErrorKind::ParseError(format!("Invalid {} ID: {}", $what, s)).into()
+                })
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                Display::fmt(&self.0, f)
+            }
+        }
+    };
+}
+
+id_type!(
+    /// A unique identifier for a source.
+    SourceId,
+    "source"
+);
+id_type!(
+    /// A unique identifier for a submission.
+    SubmissionId,
+    "submission"
+);
+id_type!(
+    /// A unique identifier for a reply.
+    ReplyId,
+    "reply"
+);
+id_type!(
+    /// A unique identifier for a user (journalist).
+    JournalistId,
+    "journalist"
+);
 
 /// Generic error message.
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Response {
     message: String,
 }
 
 impl Response {
+    pub(crate) fn new(message: String) -> Self {
+        Self { message }
+    }
+
     /// The human friendly error message.
     pub fn message(&self) -> &str {
         &self.message
@@ -20,7 +92,7 @@ impl Response {
 }
 
 /// Metadata for all sources.
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Sources {
     sources: Vec<Source>,
 }
@@ -30,40 +102,62 @@ impl Sources {
     pub fn sources(&self) -> &[Source] {
         &self.sources
     }
+
+    /// Filter and sort the sources.
+    pub fn query(&self) -> SourceQuery {
+        SourceQuery::new(&self.sources)
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Source {
-    uuid: Uuid,
+    uuid: SourceId,
     #[serde(rename = "flagged")]
-    is_flagged: bool,
+    is_flagged: Option<bool>,
+    #[serde(default)]
+    is_starred: bool,
     last_updated: DateTime<Utc>,
-    interaction_count: u32,
+    interaction_count: Option<u32>,
     journalist_designation: String,
-    number_of_documents: u32,
-    number_of_messages: u32,
-    public_key: String, // TODO better type
+    number_of_documents: Option<u32>,
+    number_of_messages: Option<u32>,
+    public_key: Option<String>, // TODO better type
+    key: Option<SourceKey>,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
 }
 
 impl Source {
     /// A unique identifier of the source.
-    pub fn uuid(&self) -> &Uuid {
+    pub fn uuid(&self) -> &SourceId {
         &self.uuid
     }
 
-    /// Boolean field indicating that the source has been flagged.
-    pub fn is_flagged(&self) -> bool {
+    /// Boolean field indicating that the source has been flagged. This is `None` if the server
+    /// did not report it.
+    pub fn is_flagged(&self) -> Option<bool> {
         self.is_flagged
     }
 
+    /// Boolean field indicating that the source has been starred. Older servers do not report
+    /// this, in which case it is `false`.
+    pub fn is_starred(&self) -> bool {
+        self.is_starred
+    }
+
+    pub(crate) fn set_starred(&mut self, starred: bool) {
+        self.is_starred = starred;
+    }
+
     /// Timestamp for when the source was last update.
     pub fn last_updated(&self) -> &DateTime<Utc> {
         &self.last_updated
     }
 
     /// Number of interactions with the source including number of messages or files a source
-    /// submitted and the number of replies to the source.
-    pub fn interaction_count(&self) -> u32 {
+    /// submitted and the number of replies to the source. This is `None` if the server did not
+    /// report it.
+    pub fn interaction_count(&self) -> Option<u32> {
         self.interaction_count
     }
 
@@ -72,25 +166,72 @@ impl Source {
         &self.journalist_designation
     }
 
-    /// Number of documents a source has submitted
-    pub fn number_of_documents(&self) -> u32 {
+    /// Number of documents a source has submitted, or `None` if the server did not report it.
+    pub fn number_of_documents(&self) -> Option<u32> {
         self.number_of_documents
     }
 
-    /// Number of messages a source has submitted
-    pub fn number_of_messages(&self) -> u32 {
+    /// Number of messages a source has submitted, or `None` if the server did not report it.
+    pub fn number_of_messages(&self) -> Option<u32> {
         self.number_of_messages
     }
 
     /// The source's public key as maintained by SecureDrop. Used to encyrpt messages to the
-    /// source.
-    pub fn public_key(&self) -> &str {
-        &self.public_key
+    /// source. Newer servers send it as part of `key`. This is `None` if the server did not
+    /// include the key.
+    pub fn public_key(&self) -> Option<&str> {
+        self.public_key
+            .as_ref()
+            .or_else(|| self.key.as_ref().and_then(|k| k.public.as_ref()))
+            .map(String::as_str)
+    }
+
+    /// The source's key, as sent by newer servers.
+    pub fn key(&self) -> Option<&SourceKey> {
+        self.key.as_ref()
+    }
+
+    /// Any fields sent by the server that this crate does not know about.
+    pub fn extra(&self) -> &Map<String, Value> {
+        &self.extra
+    }
+}
+
+/// A source's key and its metadata.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SourceKey {
+    #[serde(rename = "type")]
+    key_type: Option<String>,
+    public: Option<String>,
+    fingerprint: Option<String>,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+impl SourceKey {
+    /// The kind of key, such as `PGP`.
+    pub fn key_type(&self) -> Option<&str> {
+        self.key_type.as_ref().map(String::as_str)
+    }
+
+    /// The public key.
+    pub fn public(&self) -> Option<&str> {
+        self.public.as_ref().map(String::as_str)
+    }
+
+    /// The fingerprint of the key.
+    pub fn fingerprint(&self) -> Option<&str> {
+        self.fingerprint.as_ref().map(String::as_str)
+    }
+
+    /// Any fields sent by the server that this crate does not know about.
+    pub fn extra(&self) -> &Map<String, Value> {
+        &self.extra
     }
 }
 
 /// Response for the endpoint `GET /api/v1/source/<uuid:uuid:>/submissions`.
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Submissions {
     submissions: Vec<Submission>,
 }
@@ -100,18 +241,42 @@ impl Submissions {
     pub fn submissions(&self) -> &[Submission] {
         &self.submissions
     }
+
+    /// All submissions in the order they were made by the source. Submissions whose filenames
+    /// cannot be parsed are placed at the end.
+    pub fn chronological(&self) -> Vec<&Submission> {
+        let mut subs: Vec<&Submission> = self.submissions.iter().collect();
+        subs.sort_by_cached_key(|s| chronological_key(&s.filename));
+        subs
+    }
 }
 
 /// Metadata about a source submission.
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Submission {
+    uuid: SubmissionId,
+    source_uuid: SourceId,
     filename: String,
     is_read: bool,
     size: u64,
-    submission_id: u32,
+    download_url: Option<String>,
+    last_updated: Option<DateTime<Utc>>,
+    submission_id: Option<u32>,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
 }
 
 impl Submission {
+    /// A unique identifier for the submission.
+    pub fn uuid(&self) -> &SubmissionId {
+        &self.uuid
+    }
+
+    /// The unique identifier of the source who made the submission.
+    pub fn source_uuid(&self) -> &SourceId {
+        &self.source_uuid
+    }
+
     /// The SecureDrop filename (e.g., `1-uninteresting_agglutination-msg.gpg`).
     pub fn filename(&self) -> &str {
         &self.filename
@@ -127,22 +292,311 @@ impl Submission {
         self.size
     }
 
-    /// A unique identifier for the submission.
-    pub fn submission_id(&self) -> u32 {
+    /// The URL path the submission can be downloaded from (e.g.,
+    /// `/api/v1/sources/<uuid>/submissions/<uuid>/download`). Older servers do not include this.
+    pub fn download_url(&self) -> Option<&str> {
+        self.download_url.as_ref().map(String::as_str)
+    }
+
+    /// Timestamp for when the submission was last updated. Older servers do not include this.
+    pub fn last_updated(&self) -> Option<&DateTime<Utc>> {
+        self.last_updated.as_ref()
+    }
+
+    /// The legacy integer identifier for the submission. Older servers include this, but newer
+    /// servers identify submissions only by `uuid`.
+    pub fn submission_id(&self) -> Option<u32> {
         self.submission_id
     }
+
+    /// Any fields sent by the server that this crate does not know about.
+    pub fn extra(&self) -> &Map<String, Value> {
+        &self.extra
+    }
+
+    /// Parse the SecureDrop filename into its components. This returns `Err` if the filename is
+    /// not in the expected format.
+    pub fn parsed_filename(&self) -> Result<FileName> {
+        self.filename.parse()
+    }
+
+    /// The kind of submission (message or document). This returns `Err` if the filename is not in
+    /// the expected format.
+    pub fn kind(&self) -> Result<FileKind> {
+        self.parsed_filename().map(|f| f.kind)
+    }
+
+    /// Compare two submissions by the order in which they were made. Submissions whose filenames
+    /// cannot be parsed sort after those that can.
+    pub fn chronological_cmp(&self, other: &Self) -> Ordering {
+        chronological_cmp(&self.filename, &other.filename)
+    }
+}
+
+/// Compare two SecureDrop filenames by the order in which they were added to a conversation.
+/// Filenames that cannot be parsed sort after those that can.
+pub(crate) fn chronological_cmp(a: &str, b: &str) -> Ordering {
+    chronological_key(a).cmp(&chronological_key(b))
+}
+
+/// A key that sorts SecureDrop filenames in the same order as `chronological_cmp`. Parsing a
+/// filename is not free, so sorting a list should compute each key once.
+pub(crate) fn chronological_key(filename: &str) -> ::std::result::Result<FileName, String> {
+    // `Ok` sorts before `Err`, so unparseable filenames come last, ordered as strings
+    filename.parse().map_err(|_| filename.to_string())
+}
+
+/// The kind of file stored by SecureDrop, as encoded in its filename.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FileKind {
+    /// A text message from a source (`msg`).
+    Message,
+    /// A document uploaded by a source (`doc`).
+    Document,
+    /// A reply from a journalist to a source (`reply`).
+    Reply,
+    /// A kind of file not known to this crate, as it appears in the filename.
+    Unknown(String),
+}
+
+impl FileKind {
+    pub(crate) fn as_str(&self) -> &str {
+        match *self {
+            FileKind::Message => "msg",
+            FileKind::Document => "doc",
+            FileKind::Reply => "reply",
+            FileKind::Unknown(ref kind) => kind,
+        }
+    }
+}
+
+impl Display for FileKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The components of a SecureDrop filename (e.g., `1-uninteresting_agglutination-msg.gpg` or
+/// `2-uninteresting_agglutination-doc.gz.gpg`).
+///
+/// Filenames are ordered by their interaction counter, which reflects the order in which the
+/// messages, documents, and replies were added to the conversation with a source.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileName {
+    counter: u32,
+    journalist_designation: String,
+    kind: FileKind,
+    extension: String,
+}
+
+impl FileName {
+    /// The position of this file in the conversation with the source.
+    pub fn counter(&self) -> u32 {
+        self.counter
+    }
+
+    /// The source's designation with words separated by underscores (e.g.,
+    /// `uninteresting_agglutination`).
+    pub fn journalist_designation(&self) -> &str {
+        &self.journalist_designation
+    }
+
+    /// The kind of file.
+    pub fn kind(&self) -> &FileKind {
+        &self.kind
+    }
+
+    /// The extensions following the kind, without the leading `.` (e.g., `gpg` or `gz.gpg`).
+    pub fn extension(&self) -> &str {
+        &self.extension
+    }
+}
+
+impl FromStr for FileName {
+    type Err = ::error::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let err = || ErrorKind::ParseError(format!("Unparseable filename: {}", s)).into();
+
+        let mut parts = s.splitn(2, '-');
+        let counter = parts
+            .next()
+            .and_then(|c| c.parse::<u32>().ok())
+            .ok_or_else(err)?;
+        let rest = parts.next().ok_or_else(err)?;
+
+        // The designation never contains a `-`, but the kind is followed by one or more
+        // extensions (e.g., `doc.gz.gpg`).
+        let (designation, suffix) = match rest.rfind('-') {
+            Some(idx) => (&rest[..idx], &rest[idx + 1..]),
+            None => return Err(err()),
+        };
+        if designation.is_empty() {
+            return Err(err());
+        }
+
+        let mut suffix = suffix.splitn(2, '.');
+        let kind = match suffix.next() {
+            Some("msg") => FileKind::Message,
+            Some("doc") => FileKind::Document,
+            Some("reply") => FileKind::Reply,
+            Some(k) if !k.is_empty() => FileKind::Unknown(k.into()),
+            _ => return Err(err()),
+        };
+
+        Ok(Self {
+            counter,
+            journalist_designation: designation.into(),
+            kind,
+            extension: suffix.next().unwrap_or("").into(),
+        })
+    }
+}
+
+impl Display for FileName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}-{}",
+            self.counter, self.journalist_designation, self.kind
+        )?;
+        if !self.extension.is_empty() {
+            write!(f, ".{}", self.extension)?;
+        }
+        Ok(())
+    }
+}
+
+impl PartialOrd for FileName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FileName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.counter
+            .cmp(&other.counter)
+            .then_with(|| {
+                self.journalist_designation
+                    .cmp(&other.journalist_designation)
+            })
+            .then_with(|| self.kind.as_str().cmp(other.kind.as_str()))
+            .then_with(|| self.extension.cmp(&other.extension))
+    }
+}
+
+/// Response for the endpoint `GET /api/v1/source/<uuid:uuid:>/replies`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Replies {
+    replies: Vec<SentReply>,
+}
+
+impl Replies {
+    /// A list of all replies.
+    pub fn replies(&self) -> &[SentReply] {
+        &self.replies
+    }
+}
+
+/// Metadata about a reply a journalist sent to a source.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SentReply {
+    uuid: ReplyId,
+    filename: String,
+    is_deleted_by_source: bool,
+    journalist_username: String,
+    journalist_uuid: JournalistId,
+    size: u64,
+    #[serde(default)]
+    source_uuid: Option<SourceId>,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+impl SentReply {
+    /// A unique identifier for the reply.
+    pub fn uuid(&self) -> &ReplyId {
+        &self.uuid
+    }
+
+    /// The SecureDrop filename (e.g., `3-uninteresting_agglutination-reply.gpg`).
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Flag for whether or not the source has deleted the reply.
+    pub fn is_deleted_by_source(&self) -> bool {
+        self.is_deleted_by_source
+    }
+
+    /// The username of the journalist who sent the reply.
+    pub fn journalist_username(&self) -> &str {
+        &self.journalist_username
+    }
+
+    /// A unique identifier of the journalist who sent the reply.
+    pub fn journalist_uuid(&self) -> &JournalistId {
+        &self.journalist_uuid
+    }
+
+    /// The size of the reply in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// A unique identifier of the source the reply was sent to. Older servers only include this
+    /// in replies returned by `sync`.
+    pub fn source_uuid(&self) -> Option<&SourceId> {
+        self.source_uuid.as_ref()
+    }
+
+    pub(crate) fn set_source_uuid(&mut self, source_uuid: SourceId) {
+        self.source_uuid = Some(source_uuid);
+    }
+
+    /// Any fields sent by the server that this crate does not know about.
+    pub fn extra(&self) -> &Map<String, Value> {
+        &self.extra
+    }
+
+    /// Parse the SecureDrop filename into its components. This returns `Err` if the filename is
+    /// not in the expected format.
+    pub fn parsed_filename(&self) -> Result<FileName> {
+        self.filename.parse()
+    }
 }
 
 /// A pre-encrypted reply to a source.
-#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+///
+/// Each reply carries a UUID generated by the client so that, if sending it fails part way (e.g.,
+/// with a timeout), `Client::reply_exists` can tell whether the server stored it before it is sent
+/// again.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Reply {
+    // Replies serialized before UUIDs were added (e.g., in an outbox) get a fresh one
+    #[serde(default = "random_reply_id")]
+    uuid: ReplyId,
     reply: String,
 }
 
+fn random_reply_id() -> ReplyId {
+    ReplyId::new(Uuid::new_v4())
+}
+
 impl Reply {
-    /// Create new `Reply`. This returns `Err` if the message does not appear to be PGP encrypted
-    /// and in PEM format.
+    /// Create new `Reply` with a random UUID. This returns `Err` if the message does not appear to
+    /// be PGP encrypted and in PEM format.
     pub fn new<S>(reply: S) -> Result<Self>
+    where
+        S: Into<String>,
+    {
+        Self::with_uuid(random_reply_id(), reply)
+    }
+
+    /// Create new `Reply` with the given UUID. This returns `Err` if the message does not appear
+    /// to be PGP encrypted and in PEM format.
+    pub fn with_uuid<S>(uuid: ReplyId, reply: S) -> Result<Self>
     where
         S: Into<String>,
     {
@@ -152,18 +606,93 @@ impl Reply {
         {
             Err(ErrorKind::ClientError("Mesage not PGP encrypted".into()).into())
         } else {
-            Ok(Self { reply })
+            Ok(Self { uuid, reply })
+        }
+    }
+
+    /// The UUID the reply will be stored under.
+    pub fn uuid(&self) -> &ReplyId {
+        &self.uuid
+    }
+}
+
+/// Submissions and replies to mark as seen by the current user.
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+pub struct Seen {
+    files: Vec<SubmissionId>,
+    messages: Vec<SubmissionId>,
+    replies: Vec<ReplyId>,
+}
+
+impl Seen {
+    /// Create an empty `Seen`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a submission. The server needs to know whether it is a message or a file, so this
+    /// returns `Err` if the submission's filename cannot be parsed.
+    pub fn add_submission(&mut self, submission: &Submission) -> Result<()> {
+        match submission.kind()? {
+            FileKind::Message => self.messages.push(*submission.uuid()),
+            FileKind::Document => self.files.push(*submission.uuid()),
+            FileKind::Reply | FileKind::Unknown(_) => {
+                let msg = format!("Not a message or document: {}", submission.filename());
+                return Err(ErrorKind::ClientError(msg).into());
+            }
         }
+        Ok(())
+    }
+
+    /// Add a reply.
+    pub fn add_reply(&mut self, reply: &ReplyId) {
+        self.replies.push(*reply);
+    }
+
+    /// Whether nothing has been added.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.messages.is_empty() && self.replies.is_empty()
+    }
+}
+
+/// Response for the endpoint `POST /api/v1/sources/<uuid:uuid:>/reply`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReplyCreated {
+    message: String,
+    uuid: Option<ReplyId>,
+    filename: Option<String>,
+}
+
+impl ReplyCreated {
+    /// The human friendly status message.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// A unique identifier of the stored reply. Older servers do not include this and ignore the
+    /// UUID sent by the client.
+    pub fn uuid(&self) -> Option<&ReplyId> {
+        self.uuid.as_ref()
+    }
+
+    /// The filename the reply was stored under. Older servers do not include this.
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_ref().map(String::as_str)
     }
 }
 
 /// Information about the current logged in user (journalist).
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct User {
     user: UserInner,
 }
 
 impl User {
+    /// A unique identifier of the user. Older servers do not include this.
+    pub fn uuid(&self) -> Option<&JournalistId> {
+        self.user.uuid.as_ref()
+    }
+
     /// Boolean flag for whether or not the user is a SecureDrop administrator.
     pub fn is_admin(&self) -> bool {
         self.user.is_admin
@@ -178,11 +707,224 @@ impl User {
     pub fn username(&self) -> &str {
         &self.user.username
     }
+
+    /// Any fields sent by the server that this crate does not know about.
+    pub fn extra(&self) -> &Map<String, Value> {
+        &self.user.extra
+    }
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 struct UserInner {
+    uuid: Option<JournalistId>,
     is_admin: bool,
     last_login: DateTime<Utc>,
     username: String,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+/// Response for the endpoint `GET /api/v1/users`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Users {
+    users: Vec<Journalist>,
+}
+
+impl Users {
+    /// A list of all journalists.
+    pub fn users(&self) -> &[Journalist] {
+        &self.users
+    }
+}
+
+/// Public information about a journalist.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Journalist {
+    uuid: JournalistId,
+    username: String,
+    #[serde(default)]
+    first_name: Option<String>,
+    #[serde(default)]
+    last_name: Option<String>,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+impl Journalist {
+    /// A unique identifier of the journalist.
+    pub fn uuid(&self) -> &JournalistId {
+        &self.uuid
+    }
+
+    /// The journalist's username.
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// The journalist's first name, if set.
+    pub fn first_name(&self) -> Option<&str> {
+        self.first_name.as_ref().map(String::as_str)
+    }
+
+    /// The journalist's last name, if set.
+    pub fn last_name(&self) -> Option<&str> {
+        self.last_name.as_ref().map(String::as_str)
+    }
+
+    /// Any fields sent by the server that this crate does not know about.
+    pub fn extra(&self) -> &Map<String, Value> {
+        &self.extra
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use json;
+
+    #[test]
+    fn ids_serialize_as_plain_uuids() {
+        let raw = "\"8c3b4d6e-1c4e-4d2a-9a0b-6b1f2f2c7e11\"";
+        let id: SourceId = json::from_str(raw).unwrap();
+        assert_eq!(id, raw.trim_matches('"').parse().unwrap());
+        assert_eq!(json::to_string(&id).unwrap(), raw);
+
+        let err = "not-a-uuid".parse::<SourceId>().unwrap_err();
+        let msg = "Invalid source ID: not-a-uuid".to_string();
+        assert_eq!(err.kind(), &ErrorKind::ParseError(msg));
+    }
+
+    #[test]
+    fn sources_tolerate_missing_fields() {
+        let raw = r#"{
+            "uuid": "8c3b4d6e-1c4e-4d2a-9a0b-6b1f2f2c7e11",
+            "last_updated": "2018-10-09T21:44:02.346357Z",
+            "journalist_designation": "uninteresting agglutination"
+        }"#;
+        let source: Source = json::from_str(raw).unwrap();
+        assert_eq!(source.is_flagged(), None);
+        assert_eq!(source.interaction_count(), None);
+        assert_eq!(source.number_of_documents(), None);
+        assert_eq!(source.number_of_messages(), None);
+        assert_eq!(source.public_key(), None);
+    }
+
+    #[test]
+    fn replies_without_uuid_get_one() {
+        // Replies queued before they carried a UUID
+        let raw = r#"{"reply": "-----BEGIN PGP MESSAGE-----\nhi\n-----END PGP MESSAGE-----"}"#;
+        let first: Reply = json::from_str(raw).unwrap();
+        let second: Reply = json::from_str(raw).unwrap();
+        assert_ne!(first.uuid(), second.uuid());
+        assert_eq!(
+            json::from_str::<Reply>(&json::to_string(&first).unwrap()).unwrap(),
+            first
+        );
+    }
+
+    #[test]
+    fn user_uuid_is_optional() {
+        // Servers before 0.9.0 do not identify users by UUID
+        let raw = r#"{
+            "user": {
+                "is_admin": false,
+                "last_login": "2018-06-14T19:20:03.912671Z",
+                "username": "journalist"
+            }
+        }"#;
+        let user: User = json::from_str(raw).unwrap();
+        assert_eq!(user.uuid(), None);
+
+        let mut raw: Value = json::from_str(raw).unwrap();
+        raw["user"]["uuid"] = "b5a3e3f1-5d0c-4a56-9a3e-0f1d2c3b4a59".into();
+        let user: User = json::from_value(raw).unwrap();
+        assert_eq!(
+            user.uuid(),
+            Some(&"b5a3e3f1-5d0c-4a56-9a3e-0f1d2c3b4a59".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn submissions_are_identified_by_uuid() {
+        let raw = r#"{
+            "uuid": "2a6c0f5e-8a55-4a3f-b8a0-2a0c1b2f1f4d",
+            "source_uuid": "8c3b4d6e-1c4e-4d2a-9a0b-6b1f2f2c7e11",
+            "filename": "1-uninteresting_agglutination-msg.gpg",
+            "is_read": false,
+            "size": 604,
+            "last_updated": "2018-10-09T21:40:12.775310Z"
+        }"#;
+        let sub: Submission = json::from_str(raw).unwrap();
+        assert_eq!(
+            sub.uuid(),
+            &"2a6c0f5e-8a55-4a3f-b8a0-2a0c1b2f1f4d".parse().unwrap()
+        );
+        assert_eq!(
+            sub.last_updated(),
+            Some(&"2018-10-09T21:40:12.775310Z".parse().unwrap())
+        );
+        assert_eq!(sub.submission_id(), None);
+
+        // Older servers send a legacy integer ID and no `last_updated`
+        let raw = r#"{
+            "uuid": "2a6c0f5e-8a55-4a3f-b8a0-2a0c1b2f1f4d",
+            "source_uuid": "8c3b4d6e-1c4e-4d2a-9a0b-6b1f2f2c7e11",
+            "submission_id": 7,
+            "filename": "1-uninteresting_agglutination-msg.gpg",
+            "is_read": false,
+            "size": 604
+        }"#;
+        let sub: Submission = json::from_str(raw).unwrap();
+        assert_eq!(sub.last_updated(), None);
+        assert_eq!(sub.submission_id(), Some(7));
+
+        let mut raw: Value = json::from_str(raw).unwrap();
+        raw["uuid"] = "not a uuid".into();
+        assert!(json::from_value::<Submission>(raw).is_err());
+    }
+
+    #[test]
+    fn parse_filenames() {
+        let msg: FileName = "1-uninteresting_agglutination-msg.gpg".parse().unwrap();
+        assert_eq!(msg.counter(), 1);
+        assert_eq!(msg.journalist_designation(), "uninteresting_agglutination");
+        assert_eq!(msg.kind(), &FileKind::Message);
+
+        let doc: FileName = "12-uninteresting_agglutination-doc.gz.gpg".parse().unwrap();
+        assert_eq!(doc.counter(), 12);
+        assert_eq!(doc.kind(), &FileKind::Document);
+        assert_eq!(doc.extension(), "gz.gpg");
+        assert_eq!(doc.to_string(), "12-uninteresting_agglutination-doc.gz.gpg");
+
+        let reply: FileName = "3-uninteresting_agglutination-reply.gpg".parse().unwrap();
+        assert_eq!(reply.kind(), &FileKind::Reply);
+
+        let unknown: FileName = "4-uninteresting_agglutination-voice.ogg.gpg"
+            .parse()
+            .unwrap();
+        assert_eq!(unknown.kind(), &FileKind::Unknown("voice".into()));
+        assert_eq!(
+            unknown.to_string(),
+            "4-uninteresting_agglutination-voice.ogg.gpg"
+        );
+
+        assert!(msg < reply);
+        assert!(reply < doc);
+    }
+
+    #[test]
+    fn reject_bad_filenames() {
+        for name in &[
+            "",
+            "msg.gpg",
+            "x-uninteresting_agglutination-msg.gpg",
+            "1-msg.gpg",
+            "1--msg.gpg",
+            "1-uninteresting_agglutination-.gpg",
+        ] {
+            let err = name.parse::<FileName>().unwrap_err();
+            let msg = format!("Unparseable filename: {}", name);
+            assert_eq!(err.kind(), &ErrorKind::ParseError(msg));
+        }
+    }
 }
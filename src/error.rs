@@ -19,6 +19,7 @@ impl Display for ErrorResponse {
 #[derive(Debug)]
 pub struct Error {
     inner: Context<ErrorKind>,
+    status: Option<u16>,
 }
 
 impl Error {
@@ -26,6 +27,7 @@ impl Error {
     pub fn new(kind: ErrorKind) -> Self {
         Self {
             inner: Context::new(kind),
+            status: None,
         }
     }
 
@@ -33,18 +35,26 @@ impl Error {
     pub fn kind(&self) -> &ErrorKind {
         self.inner.get_context()
     }
+
+    /// The HTTP status code of the response that caused this error, if any.
+    pub fn status(&self) -> Option<u16> {
+        self.status
+    }
+
+    pub(crate) fn with_status(mut self, status: u16) -> Self {
+        self.status = Some(status);
+        self
+    }
 }
 
 impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Error {
-        Error {
-            inner: Context::new(kind),
-        }
+        Error::new(kind)
     }
 }
 
 impl Fail for Error {
-    fn cause(&self) -> Option<&Fail> {
+    fn cause(&self) -> Option<&dyn Fail> {
         self.inner.cause()
     }
 
@@ -61,7 +71,10 @@ impl Display for Error {
 
 impl From<Context<ErrorKind>> for Error {
     fn from(inner: Context<ErrorKind>) -> Error {
-        Error { inner }
+        Error {
+            inner,
+            status: None,
+        }
     }
 }
 
@@ -84,6 +97,10 @@ pub enum ErrorKind {
     #[fail(display = "Network error")]
     NetworkError,
 
+    /// A value (e.g., a SecureDrop filename) was not in the expected format.
+    #[fail(display = "Parse error: {}", _0)]
+    ParseError(String),
+
     /// Error reserved for bugs in this crate. If is surfaces, please report it.
     #[fail(display = "Programming error (this is a bug): {}", _0)]
     ProgrammingError(String),
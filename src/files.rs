@@ -0,0 +1,636 @@
+//! A managed directory of downloaded submissions and replies.
+//!
+//! This module is only available with the `files` feature enabled.
+//!
+//! A `FileStore` downloads files into a directory laid out as `<root>/<source uuid>/<file uuid>`
+//! and keeps a manifest (`<root>/manifest.json`) recording where each file came from, its size,
+//! and its SHA-256 digest. Files are written to a temporary file and renamed into place, so a
+//! failed download never leaves a partial file behind.
+//!
+//! Files can optionally be encrypted at rest with an `EncryptionKey` (ChaCha20-Poly1305 in 64 KiB
+//! chunks, so large files are never held in memory). Digests are of the stored bytes, so
+//! `FileStore::verify` does not need the key.
+//!
+//! ```no_run
+//! # extern crate securedrop_api;
+//! use securedrop_api::auth::UserPassTotp;
+//! use securedrop_api::files::{EncryptionKey, FileStore};
+//! use securedrop_api::sync::{MemoryStore, Syncer};
+//! use securedrop_api::Client;
+//! use std::io::Read;
+//!
+//! # fn main() {
+//! # let creds = UserPassTotp::new("journalist".into(), "pass".into(), "123456".into());
+//! let client = Client::new("http://localhost:8081".parse().unwrap(), creds, None).unwrap();
+//! let mut files = FileStore::open_encrypted("downloads", EncryptionKey::generate()).unwrap();
+//! let mut syncer = Syncer::new(MemoryStore::new());
+//!
+//! let changes = syncer.sync(&client).unwrap();
+//! for submission in changes.submissions().added() {
+//!     files.download_submission(&client, submission).unwrap();
+//! }
+//! // Remove local copies of anything deleted on the server
+//! files.purge(&changes).unwrap();
+//! # }
+//! ```
+
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::stream::{DecryptorBE32, EncryptorBE32};
+use chacha20poly1305::aead::{KeyInit, OsRng};
+use chacha20poly1305::ChaCha20Poly1305;
+use chrono::{DateTime, Utc};
+use json;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug, Write as FmtWrite};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use super::Result;
+use auth::{create_private_dir, write_private};
+use client::Client;
+use data::{ReplyId, SentReply, SourceId, Submission, SubmissionId};
+use error::{Error, ErrorKind};
+use sync::ChangeSet;
+
+const MANIFEST: &str = "manifest.json";
+
+/// Identifies encrypted files and the version of their format.
+const MAGIC: &[u8] = b"SDFS\x01";
+
+/// The size of each encrypted chunk of plaintext.
+const CHUNK: usize = 64 * 1024;
+
+/// The size of the authentication tag appended to each chunk.
+const TAG: usize = 16;
+
+/// The size of the random nonce prefix stored after `MAGIC`.
+const NONCE_PREFIX: usize = 7;
+
+/// A 256-bit key for encrypting files at rest. The key is wiped from memory on drop and is never
+/// printed by `Debug`.
+#[derive(Clone)]
+pub struct EncryptionKey(Zeroizing<[u8; 32]>);
+
+impl EncryptionKey {
+    /// Generate a random key.
+    pub fn generate() -> Self {
+        let mut key = [0; 32];
+        OsRng.fill_bytes(&mut key);
+        Self::from_bytes(key)
+    }
+
+    /// Use an existing key (e.g., one kept in the OS keyring).
+    pub fn from_bytes(key: [u8; 32]) -> Self {
+        EncryptionKey(Zeroizing::new(key))
+    }
+
+    /// Access the raw key so that it can be stored. Take care not to log or otherwise copy it.
+    pub fn expose_secret(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(self.0.as_ref().into())
+    }
+}
+
+impl Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("EncryptionKey([REDACTED])")
+    }
+}
+
+/// Identifies a stored file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileId {
+    /// A file submitted by a source.
+    Submission(SubmissionId),
+    /// A reply sent to a source.
+    Reply(ReplyId),
+}
+
+impl FileId {
+    fn uuid(&self) -> &Uuid {
+        match *self {
+            FileId::Submission(ref id) => id.uuid(),
+            FileId::Reply(ref id) => id.uuid(),
+        }
+    }
+}
+
+/// A manifest entry describing a downloaded file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredFile {
+    id: FileId,
+    source_uuid: SourceId,
+    filename: String,
+    size: u64,
+    sha256: String,
+    encrypted: bool,
+    downloaded_at: DateTime<Utc>,
+}
+
+impl StoredFile {
+    /// The ID of the submission or reply.
+    pub fn id(&self) -> &FileId {
+        &self.id
+    }
+
+    /// The source the file belongs to.
+    pub fn source_uuid(&self) -> &SourceId {
+        &self.source_uuid
+    }
+
+    /// The SecureDrop filename (e.g., `1-uninteresting_agglutination-msg.gpg`).
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// The number of bytes on disk.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The lowercase hex SHA-256 digest of the bytes on disk.
+    pub fn sha256(&self) -> &str {
+        &self.sha256
+    }
+
+    /// Whether the file is encrypted at rest.
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    /// When the file was downloaded.
+    pub fn downloaded_at(&self) -> &DateTime<Utc> {
+        &self.downloaded_at
+    }
+
+    fn relative_path(&self) -> PathBuf {
+        Path::new(&self.source_uuid.to_string()).join(self.id.uuid().to_string())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    files: Vec<StoredFile>,
+}
+
+/// A managed directory of downloaded files.
+#[derive(Debug)]
+pub struct FileStore {
+    root: PathBuf,
+    key: Option<EncryptionKey>,
+    files: BTreeMap<FileId, StoredFile>,
+}
+
+impl FileStore {
+    /// Open (or create) a store at `root`. Files are stored unencrypted.
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<Self> {
+        Self::open_with(root.as_ref(), None)
+    }
+
+    /// Open (or create) a store at `root`. New files are encrypted with `key`.
+    pub fn open_encrypted<P: AsRef<Path>>(root: P, key: EncryptionKey) -> Result<Self> {
+        Self::open_with(root.as_ref(), Some(key))
+    }
+
+    fn open_with(root: &Path, key: Option<EncryptionKey>) -> Result<Self> {
+        create_private_dir(root)?;
+        let files = match File::open(root.join(MANIFEST)) {
+            Ok(file) => {
+                let manifest: Manifest = json::from_reader(BufReader::new(file))
+                    .map_err(|e| ErrorKind::IO(format!("Invalid manifest: {}", e)))?;
+                manifest.files.into_iter().map(|f| (f.id, f)).collect()
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(io_error(e)),
+        };
+        Ok(Self {
+            root: root.to_path_buf(),
+            key,
+            files,
+        })
+    }
+
+    /// The root directory of the store.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// All stored files.
+    pub fn files(&self) -> Vec<&StoredFile> {
+        self.files.values().collect()
+    }
+
+    /// Look up a stored file.
+    pub fn get(&self, id: &FileId) -> Option<&StoredFile> {
+        self.files.get(id)
+    }
+
+    /// The absolute path of a stored file. If the file is encrypted, use `FileStore::read` to
+    /// access its contents.
+    pub fn path(&self, file: &StoredFile) -> PathBuf {
+        self.root.join(file.relative_path())
+    }
+
+    /// Download a submission into the store, replacing any existing copy.
+    pub fn download_submission(
+        &mut self,
+        client: &Client,
+        submission: &Submission,
+    ) -> Result<&StoredFile> {
+        let source = *submission.source_uuid();
+        let id = *submission.uuid();
+        self.download(FileId::Submission(id), source, submission.filename(), |w| {
+            client.download_submission(&source, &id, w)
+        })
+    }
+
+    /// Download a reply to the given source into the store, replacing any existing copy.
+    pub fn download_reply(
+        &mut self,
+        client: &Client,
+        source: &SourceId,
+        reply: &SentReply,
+    ) -> Result<&StoredFile> {
+        let id = *reply.uuid();
+        self.download(FileId::Reply(id), *source, reply.filename(), |w| {
+            client.download_reply(source, &id, w)
+        })
+    }
+
+    fn download<F>(
+        &mut self,
+        id: FileId,
+        source: SourceId,
+        filename: &str,
+        fetch: F,
+    ) -> Result<&StoredFile>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<()>,
+    {
+        let dir = self.root.join(source.to_string());
+        create_private_dir(&dir)?;
+        let tmp = dir.join(format!(".{}.part", id.uuid()));
+
+        let result = self.write_file(&tmp, fetch);
+        let (size, sha256) = match result {
+            Ok(x) => x,
+            Err(e) => {
+                let _ = fs::remove_file(&tmp);
+                return Err(e);
+            }
+        };
+
+        let file = StoredFile {
+            id,
+            source_uuid: source,
+            filename: filename.to_string(),
+            size,
+            sha256,
+            encrypted: self.key.is_some(),
+            downloaded_at: Utc::now(),
+        };
+        fs::rename(&tmp, self.path(&file)).map_err(io_error)?;
+        self.files.insert(id, file);
+        self.save_manifest()?;
+        Ok(&self.files[&id])
+    }
+
+    fn write_file<F>(&self, path: &Path, fetch: F) -> Result<(u64, String)>
+    where
+        F: FnOnce(&mut dyn Write) -> Result<()>,
+    {
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options.open(path).map_err(io_error)?;
+        let hashed = HashWriter::new(BufWriter::new(file));
+
+        let hashed = match self.key {
+            Some(ref key) => {
+                let mut writer = EncryptWriter::new(hashed, key).map_err(io_error)?;
+                fetch(&mut writer)?;
+                writer.finish().map_err(io_error)?
+            }
+            None => {
+                let mut writer = hashed;
+                fetch(&mut writer)?;
+                writer
+            }
+        };
+
+        let (writer, size, sha256) = hashed.finish();
+        let file = writer.into_inner().map_err(|e| io_error(e.into_error()))?;
+        file.sync_all().map_err(io_error)?;
+        Ok((size, sha256))
+    }
+
+    /// Read the contents of a stored file, decrypting it if needed.
+    pub fn read(&self, id: &FileId) -> Result<Box<dyn Read + Send>> {
+        let file = self.stored(id)?;
+        let reader = BufReader::new(File::open(self.path(file)).map_err(io_error)?);
+        if !file.encrypted {
+            return Ok(Box::new(reader));
+        }
+        match self.key {
+            Some(ref key) => Ok(Box::new(DecryptReader::new(reader, key).map_err(io_error)?)),
+            None => {
+                let msg = "File is encrypted but no key was given";
+                Err(ErrorKind::ClientError(msg.into()).into())
+            }
+        }
+    }
+
+    /// Check that a stored file exists and matches the size and digest in the manifest.
+    pub fn verify(&self, id: &FileId) -> Result<bool> {
+        let file = self.stored(id)?;
+        let mut reader = match File::open(self.path(file)) {
+            Ok(f) => f,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(io_error(e)),
+        };
+        let mut hashed = HashWriter::new(io::sink());
+        io::copy(&mut reader, &mut hashed).map_err(io_error)?;
+        let (_, size, sha256) = hashed.finish();
+        Ok(size == file.size && sha256 == file.sha256)
+    }
+
+    /// Delete a stored file. Returns `false` if it was not stored.
+    pub fn remove(&mut self, id: &FileId) -> Result<bool> {
+        let file = match self.files.remove(id) {
+            Some(file) => file,
+            None => return Ok(false),
+        };
+        match fs::remove_file(self.path(&file)) {
+            Err(ref e) if e.kind() != io::ErrorKind::NotFound => {
+                self.files.insert(*id, file);
+                return Err(ErrorKind::IO(e.to_string()).into());
+            }
+            _ => (),
+        }
+        // Only succeeds once the source has no more files
+        let _ = fs::remove_dir(self.root.join(file.source_uuid.to_string()));
+        self.save_manifest()?;
+        Ok(true)
+    }
+
+    /// Delete the local copies of everything a sync found was deleted on the server. Returns the
+    /// IDs of the files that were removed.
+    pub fn purge(&mut self, changes: &ChangeSet) -> Result<Vec<FileId>> {
+        let deleted_sources = changes.sources().deleted();
+        let ids: Vec<FileId> = self
+            .files
+            .values()
+            .filter(|f| {
+                deleted_sources.contains(&f.source_uuid)
+                    || match f.id {
+                        FileId::Submission(ref id) => changes.submissions().deleted().contains(id),
+                        FileId::Reply(ref id) => changes.replies().deleted().contains(id),
+                    }
+            })
+            .map(|f| f.id)
+            .collect();
+        for id in &ids {
+            self.remove(id)?;
+        }
+        Ok(ids)
+    }
+
+    fn stored(&self, id: &FileId) -> Result<&StoredFile> {
+        self.files
+            .get(id)
+            .ok_or_else(|| ErrorKind::ClientError("File is not stored".into()).into())
+    }
+
+    fn save_manifest(&self) -> Result<()> {
+        let manifest = Manifest {
+            version: 1,
+            files: self.files.values().cloned().collect(),
+        };
+        let bytes = json::to_vec_pretty(&manifest)
+            .map_err(|e| ErrorKind::ProgrammingError(e.to_string()))?;
+        write_private(&self.root.join(MANIFEST), &bytes)
+    }
+}
+
+fn io_error(e: io::Error) -> Error {
+    ErrorKind::IO(e.to_string()).into()
+}
+
+fn crypto_error<E>(_: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Decryption failed")
+}
+
+/// Counts and hashes everything written through it.
+struct HashWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<W: Write> HashWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            size: 0,
+        }
+    }
+
+    fn finish(self) -> (W, u64, String) {
+        let mut hex = String::with_capacity(64);
+        for b in self.hasher.finalize() {
+            let _ = write!(hex, "{:02x}", b);
+        }
+        (self.inner, self.size, hex)
+    }
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Encrypts everything written through it in `CHUNK` sized pieces. `finish` must be called to
+/// write the final chunk.
+struct EncryptWriter<W> {
+    inner: W,
+    encryptor: EncryptorBE32<ChaCha20Poly1305>,
+    buf: Zeroizing<Vec<u8>>,
+}
+
+impl<W: Write> EncryptWriter<W> {
+    fn new(mut inner: W, key: &EncryptionKey) -> io::Result<Self> {
+        let mut nonce = [0; NONCE_PREFIX];
+        OsRng.fill_bytes(&mut nonce);
+        inner.write_all(MAGIC)?;
+        inner.write_all(&nonce)?;
+        Ok(Self {
+            inner,
+            encryptor: EncryptorBE32::from_aead(key.cipher(), (&nonce).into()),
+            buf: Zeroizing::new(Vec::with_capacity(CHUNK)),
+        })
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        let last = self
+            .encryptor
+            .encrypt_last(&self.buf[..])
+            .map_err(crypto_error)?;
+        self.inner.write_all(&last)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, mut data: &[u8]) -> io::Result<usize> {
+        let len = data.len();
+        while !data.is_empty() {
+            // A full chunk is only sealed once more data arrives, since the last chunk is
+            // encrypted differently.
+            if self.buf.len() == CHUNK {
+                let chunk = self
+                    .encryptor
+                    .encrypt_next(&self.buf[..])
+                    .map_err(crypto_error)?;
+                self.inner.write_all(&chunk)?;
+                self.buf.clear();
+            }
+            let n = (CHUNK - self.buf.len()).min(data.len());
+            self.buf.extend_from_slice(&data[..n]);
+            data = &data[n..];
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decrypts a file written by `EncryptWriter`.
+struct DecryptReader<R> {
+    inner: R,
+    decryptor: Option<DecryptorBE32<ChaCha20Poly1305>>,
+    pending: Vec<u8>,
+    plain: Zeroizing<Vec<u8>>,
+    pos: usize,
+}
+
+impl<R: Read> DecryptReader<R> {
+    fn new(mut inner: R, key: &EncryptionKey) -> io::Result<Self> {
+        let mut header = [0; MAGIC.len() + NONCE_PREFIX];
+        inner.read_exact(&mut header)?;
+        if &header[..MAGIC.len()] != MAGIC {
+            return Err(crypto_error(()));
+        }
+        let nonce = &header[MAGIC.len()..];
+        Ok(Self {
+            inner,
+            decryptor: Some(DecryptorBE32::from_aead(key.cipher(), nonce.into())),
+            pending: Vec::new(),
+            plain: Zeroizing::new(Vec::new()),
+            pos: 0,
+        })
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let segment = CHUNK + TAG;
+        let mut data = mem::take(&mut self.pending);
+        // Read one byte past the segment to find out whether this is the last one
+        (&mut self.inner)
+            .take((segment + 1 - data.len()) as u64)
+            .read_to_end(&mut data)?;
+
+        let plain = if data.len() > segment {
+            self.pending = data.split_off(segment);
+            match self.decryptor {
+                Some(ref mut d) => d.decrypt_next(&data[..]).map_err(crypto_error)?,
+                None => return Ok(()),
+            }
+        } else {
+            match self.decryptor.take() {
+                Some(d) => d.decrypt_last(&data[..]).map_err(crypto_error)?,
+                None => return Ok(()),
+            }
+        };
+        self.plain = Zeroizing::new(plain);
+        self.pos = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plain.len() {
+            if self.decryptor.is_none() {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let n = (self.plain.len() - self.pos).min(buf.len());
+        buf[..n].copy_from_slice(&self.plain[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encryption_round_trip() {
+        let key = EncryptionKey::generate();
+        for &len in &[0, 1, CHUNK - 1, CHUNK, CHUNK + 1, 3 * CHUNK] {
+            let plain: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let mut writer = EncryptWriter::new(Vec::new(), &key).unwrap();
+            for piece in plain.chunks(1000) {
+                writer.write_all(piece).unwrap();
+            }
+            let encrypted = writer.finish().unwrap();
+            let chunks = ((len + CHUNK - 1) / CHUNK).max(1);
+            assert_eq!(
+                encrypted.len(),
+                MAGIC.len() + NONCE_PREFIX + len + chunks * TAG
+            );
+
+            let mut decrypted = Vec::new();
+            DecryptReader::new(&encrypted[..], &key)
+                .unwrap()
+                .read_to_end(&mut decrypted)
+                .unwrap();
+            assert_eq!(decrypted, plain);
+
+            let mut truncated = encrypted.clone();
+            truncated.pop();
+            let mut out = Vec::new();
+            assert!(DecryptReader::new(&truncated[..], &key)
+                .unwrap()
+                .read_to_end(&mut out)
+                .is_err());
+        }
+    }
+}
@@ -36,7 +36,7 @@
 //!     let mut buf = Vec::new();
 //!     client.download_submission(
 //!         source.uuid(),
-//!         submissions.submissions()[0].submission_id(),
+//!         submissions.submissions()[0].uuid(),
 //!         &mut buf,
 //!     )?;
 //!
@@ -49,20 +49,55 @@
 //! }
 //! ```
 
+extern crate base32;
+#[cfg(feature = "files")]
+extern crate chacha20poly1305;
 extern crate chrono;
+extern crate crossbeam_utils;
 #[macro_use]
 extern crate failure;
+extern crate hmac;
+#[cfg(unix)]
+extern crate libc;
 extern crate reqwest;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
+#[cfg_attr(feature = "mock", macro_use)]
 extern crate serde_json as json;
+extern crate sha1;
+#[cfg(feature = "files")]
+extern crate sha2;
+#[cfg(feature = "mock")]
+extern crate tiny_http;
 extern crate uuid;
+extern crate zeroize;
 
 pub mod auth;
+pub mod bulk;
+pub mod cache;
+pub mod cassette;
 pub mod client;
+pub mod conversation;
 pub mod data;
 pub mod error;
+#[cfg(feature = "files")]
+pub mod files;
+#[cfg(feature = "mock")]
+pub mod mock;
+mod otp;
+pub mod outbox;
+pub mod page;
+pub mod proxy;
+pub mod query;
+pub mod secret;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod sync;
+pub mod transport;
+pub mod watch;
 
 pub use client::Client;
 pub use error::{Error, ErrorKind};
@@ -0,0 +1,897 @@
+//! An in-process mock SecureDrop server for testing applications built on this crate.
+//!
+//! This module is only available with the `mock` feature enabled.
+//!
+//! ```no_run
+//! # extern crate securedrop_api;
+//! use securedrop_api::Client;
+//! use securedrop_api::data::FileKind;
+//! use securedrop_api::mock::{Dataset, MockServer};
+//!
+//! # fn main() {
+//! let mut dataset = Dataset::new("journalist", "correct horse battery staple", "123456");
+//! let source = dataset.add_source("uninteresting agglutination");
+//! dataset.add_submission(&source, FileKind::Message, b"encrypted".to_vec());
+//!
+//! let server = MockServer::start(dataset).unwrap();
+//! let client = Client::new(server.url(), server.credentials(), None).unwrap();
+//! assert_eq!(client.sources().unwrap().sources().len(), 1);
+//! # }
+//! ```
+
+use chrono::{DateTime, Duration as OldDuration, Utc};
+use json::Value;
+use reqwest::Url;
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response as HttpResponse, Server};
+use uuid::Uuid;
+
+use super::Result;
+use auth::UserPassTotp;
+use data::{FileKind, FileName, JournalistId, ReplyId, SourceId, SubmissionId};
+use error::ErrorKind;
+
+/// The journalist account that is permitted to log in to the mock server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MockUser {
+    uuid: JournalistId,
+    username: String,
+    passphrase: String,
+    one_time_code: String,
+    last_login: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MockSubmission {
+    uuid: SubmissionId,
+    filename: String,
+    content: Vec<u8>,
+    is_read: bool,
+    last_updated: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MockReply {
+    uuid: ReplyId,
+    filename: String,
+    content: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MockSource {
+    uuid: SourceId,
+    journalist_designation: String,
+    is_flagged: bool,
+    is_starred: bool,
+    last_updated: DateTime<Utc>,
+    interaction_count: u32,
+    public_key: String,
+    submissions: Vec<MockSubmission>,
+    replies: Vec<MockReply>,
+}
+
+impl MockSource {
+    fn next_filename(&mut self, kind: FileKind) -> String {
+        self.interaction_count += 1;
+        self.last_updated = Utc::now();
+        let suffix = match kind {
+            FileKind::Document => "doc.gz",
+            ref kind => kind.as_str(),
+        };
+        format!(
+            "{}-{}-{}.gpg",
+            self.interaction_count,
+            self.journalist_designation.replace(' ', "_"),
+            suffix
+        )
+    }
+}
+
+/// The in-memory data served by a `MockServer`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dataset {
+    user: MockUser,
+    sources: Vec<MockSource>,
+}
+
+impl Dataset {
+    /// Create an empty dataset with a single journalist account that can log in using the given
+    /// username, passphrase, and one time code.
+    pub fn new<S1, S2, S3>(username: S1, passphrase: S2, one_time_code: S3) -> Self
+    where
+        S1: Into<String>,
+        S2: Into<String>,
+        S3: Into<String>,
+    {
+        Self {
+            user: MockUser {
+                uuid: JournalistId::new(Uuid::new_v4()),
+                username: username.into(),
+                passphrase: passphrase.into(),
+                one_time_code: one_time_code.into(),
+                last_login: Utc::now(),
+            },
+            sources: Vec::new(),
+        }
+    }
+
+    /// The ID of the journalist account.
+    pub fn journalist_uuid(&self) -> &JournalistId {
+        &self.user.uuid
+    }
+
+    /// Add a source with the given designation (e.g., `uninteresting agglutination`).
+    pub fn add_source(&mut self, journalist_designation: &str) -> SourceId {
+        let uuid = SourceId::new(Uuid::new_v4());
+        self.sources.push(MockSource {
+            uuid: uuid,
+            journalist_designation: journalist_designation.into(),
+            is_flagged: false,
+            is_starred: false,
+            last_updated: Utc::now(),
+            interaction_count: 0,
+            public_key: "-----BEGIN PGP PUBLIC KEY BLOCK-----\n-----END PGP PUBLIC KEY BLOCK-----"
+                .into(),
+            submissions: Vec::new(),
+            replies: Vec::new(),
+        });
+        uuid
+    }
+
+    /// Add a submission from a source. The `content` is served as-is when the submission is
+    /// downloaded.
+    ///
+    /// Panics if the source does not exist.
+    pub fn add_submission(
+        &mut self,
+        source: &SourceId,
+        kind: FileKind,
+        content: Vec<u8>,
+    ) -> SubmissionId {
+        let source = self.source_mut(source).expect("source does not exist");
+        let uuid = SubmissionId::new(Uuid::new_v4());
+        let filename = source.next_filename(kind);
+        source.submissions.push(MockSubmission {
+            uuid: uuid,
+            filename: filename,
+            content: content,
+            is_read: false,
+            last_updated: Utc::now(),
+        });
+        uuid
+    }
+
+    /// Add a reply to a source from the journalist.
+    ///
+    /// Panics if the source does not exist.
+    pub fn add_reply(&mut self, source: &SourceId, content: Vec<u8>) -> ReplyId {
+        let uuid = ReplyId::new(Uuid::new_v4());
+        self.push_reply(source, uuid, content)
+            .expect("source does not exist");
+        uuid
+    }
+
+    /// Remove a source and all its submissions and replies. Returns `false` if the source did not
+    /// exist.
+    pub fn remove_source(&mut self, source: &SourceId) -> bool {
+        let len = self.sources.len();
+        self.sources.retain(|s| &s.uuid != source);
+        self.sources.len() != len
+    }
+
+    /// The IDs of all sources.
+    pub fn source_ids(&self) -> Vec<SourceId> {
+        self.sources.iter().map(|s| s.uuid).collect()
+    }
+
+    /// Whether or not the source is starred. Returns `None` if the source does not exist.
+    pub fn is_starred(&self, source: &SourceId) -> Option<bool> {
+        self.source(source).map(|s| s.is_starred)
+    }
+
+    /// Set whether or not the source is starred. Returns `false` if the source did not exist.
+    pub fn set_starred(&mut self, source: &SourceId, starred: bool) -> bool {
+        match self.source_mut(source) {
+            Some(s) => {
+                s.is_starred = starred;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The IDs of all submissions from a source. Returns `None` if the source does not exist.
+    pub fn submission_ids(&self, source: &SourceId) -> Option<Vec<SubmissionId>> {
+        self.source(source)
+            .map(|s| s.submissions.iter().map(|s| s.uuid).collect())
+    }
+
+    /// Whether or not a submission has been downloaded or marked as seen. Returns `None` if the
+    /// submission does not exist.
+    pub fn is_read(&self, submission: &SubmissionId) -> Option<bool> {
+        self.sources
+            .iter()
+            .flat_map(|s| s.submissions.iter())
+            .find(|s| &s.uuid == submission)
+            .map(|s| s.is_read)
+    }
+
+    /// The contents of all replies to a source, oldest first. Returns `None` if the source does
+    /// not exist.
+    pub fn replies(&self, source: &SourceId) -> Option<Vec<Vec<u8>>> {
+        self.source(source)
+            .map(|s| s.replies.iter().map(|r| r.content.clone()).collect())
+    }
+
+    fn push_reply(&mut self, source: &SourceId, uuid: ReplyId, content: Vec<u8>) -> Option<String> {
+        let source = self.source_mut(source)?;
+        let filename = source.next_filename(FileKind::Reply);
+        source.replies.push(MockReply {
+            uuid: uuid,
+            filename: filename.clone(),
+            content: content,
+        });
+        Some(filename)
+    }
+
+    fn has_reply(&self, uuid: &ReplyId) -> bool {
+        self.sources
+            .iter()
+            .any(|s| s.replies.iter().any(|r| &r.uuid == uuid))
+    }
+
+    fn source(&self, uuid: &SourceId) -> Option<&MockSource> {
+        self.sources.iter().find(|s| &s.uuid == uuid)
+    }
+
+    fn source_mut(&mut self, uuid: &SourceId) -> Option<&mut MockSource> {
+        self.sources.iter_mut().find(|s| &s.uuid == uuid)
+    }
+}
+
+/// How the mock server paginates list endpoints when the client sends a `limit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pagination {
+    /// Return a `next_page` number.
+    Page,
+    /// Return an opaque `next_cursor`.
+    Cursor,
+    /// Return the first page with the same `next_cursor` every time, like a broken server.
+    Stuck,
+}
+
+struct State {
+    dataset: Dataset,
+    pagination: Option<Pagination>,
+    tokens: HashMap<String, DateTime<Utc>>,
+    token_lifetime: OldDuration,
+    latency: Duration,
+    failure: Option<u16>,
+    queued_failures: VecDeque<u16>,
+}
+
+/// A SecureDrop server running on a local port in a background thread. The server is shut down
+/// when this is dropped.
+pub struct MockServer {
+    url: Url,
+    credentials: UserPassTotp,
+    state: Arc<Mutex<State>>,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Start serving the given dataset on an ephemeral port on `127.0.0.1`.
+    pub fn start(dataset: Dataset) -> Result<Self> {
+        let server = Server::http("127.0.0.1:0")
+            .map_err(|e| ErrorKind::IO(format!("Failed to start mock server: {}", e)))?;
+        let url = format!("http://{}/", server.server_addr())
+            .parse()
+            .map_err(|e| ErrorKind::ProgrammingError(format!("Bad mock server URL: {}", e)))?;
+        let credentials = UserPassTotp::new(
+            dataset.user.username.clone(),
+            dataset.user.passphrase.clone(),
+            dataset.user.one_time_code.clone(),
+        );
+
+        let state = Arc::new(Mutex::new(State {
+            dataset: dataset,
+            pagination: None,
+            tokens: HashMap::new(),
+            token_lifetime: OldDuration::hours(8),
+            latency: Duration::from_millis(0),
+            failure: None,
+            queued_failures: VecDeque::new(),
+        }));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let state = state.clone();
+            let shutdown = shutdown.clone();
+            thread::spawn(move || {
+                while !shutdown.load(Ordering::SeqCst) {
+                    match server.recv_timeout(Duration::from_millis(50)) {
+                        Ok(Some(req)) => serve(&state, req),
+                        Ok(None) => (),
+                        Err(_) => break,
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            url: url,
+            credentials: credentials,
+            state: state,
+            shutdown: shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    /// The base URL of the server, suitable for passing to `Client::new`.
+    pub fn url(&self) -> Url {
+        self.url.clone()
+    }
+
+    /// Credentials that will be accepted by the server.
+    pub fn credentials(&self) -> UserPassTotp {
+        self.credentials.clone()
+    }
+
+    /// Inspect or modify the dataset while the server is running.
+    pub fn with_dataset<F, T>(&self, func: F) -> T
+    where
+        F: FnOnce(&mut Dataset) -> T,
+    {
+        func(&mut self.lock().dataset)
+    }
+
+    /// Respond to the next request with the given status code instead of handling it. Calling this
+    /// multiple times queues multiple failures.
+    pub fn fail_next(&self, status: u16) {
+        self.lock().queued_failures.push_back(status);
+    }
+
+    /// Respond to every request with the given status code until this is called with `None`.
+    pub fn set_failure(&self, status: Option<u16>) {
+        self.lock().failure = status;
+    }
+
+    /// Paginate the source and submission lists. By default (`None`) the server behaves like
+    /// older servers and ignores `limit`.
+    pub fn set_pagination(&self, pagination: Option<Pagination>) {
+        self.lock().pagination = pagination;
+    }
+
+    /// Delay every response by the given duration.
+    pub fn set_latency(&self, latency: Duration) {
+        self.lock().latency = latency;
+    }
+
+    /// Set how long newly issued tokens are valid for. Defaults to 8 hours.
+    pub fn set_token_lifetime(&self, lifetime: Duration) {
+        let lifetime = OldDuration::from_std(lifetime).expect("token lifetime out of range");
+        self.lock().token_lifetime = lifetime;
+    }
+
+    /// Immediately invalidate all issued tokens.
+    pub fn expire_tokens(&self) {
+        self.lock().tokens.clear();
+    }
+
+    fn lock(&self) -> ::std::sync::MutexGuard<State> {
+        // a panic while holding the lock can only come from a bug in this module, and the state
+        // is still usable for test purposes
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+struct MockResponse {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl MockResponse {
+    fn json(status: u16, value: Value) -> Self {
+        Self {
+            status: status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn message(status: u16, message: &str) -> Self {
+        Self::json(status, json!({ "message": message }))
+    }
+
+    fn not_found() -> Self {
+        Self::message(404, "Not Found")
+    }
+
+    fn file(content: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/pgp-encrypted",
+            body: content,
+        }
+    }
+}
+
+fn serve(state: &Mutex<State>, mut req: Request) {
+    let mut body = Vec::new();
+    let _ = req.as_reader().read_to_end(&mut body);
+    let token = req
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .and_then(|h| {
+            let value = h.value.as_str();
+            if value.starts_with("Token ") {
+                Some(value["Token ".len()..].to_string())
+            } else {
+                None
+            }
+        });
+    let if_none_match = req
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("If-None-Match"))
+        .map(|h| h.value.as_str().to_string());
+    let mut url = req.url().splitn(2, '?');
+    let path = url.next().unwrap_or("").to_string();
+    let query: HashMap<String, String> = url
+        .next()
+        .unwrap_or("")
+        .split('&')
+        .filter_map(|pair| {
+            let mut pair = pair.splitn(2, '=');
+            Some((pair.next()?.to_string(), pair.next()?.to_string()))
+        })
+        .collect();
+
+    let (latency, reply) = {
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        let failure = state.queued_failures.pop_front().or(state.failure);
+        let reply = match failure {
+            Some(status) => MockResponse::message(status, "Injected failure"),
+            None => route(
+                &mut state,
+                req.method(),
+                &path,
+                &query,
+                token.as_ref(),
+                &body,
+            ),
+        };
+        (state.latency, reply)
+    };
+
+    if latency > Duration::from_millis(0) {
+        thread::sleep(latency);
+    }
+
+    // Let clients revalidate JSON responses the way a server behind a caching proxy would
+    let etag = if *req.method() == Method::Get
+        && reply.status == 200
+        && reply.content_type == "application/json"
+    {
+        let mut etag = String::from("\"");
+        for b in Sha1::digest(&reply.body) {
+            let _ = write!(etag, "{:02x}", b);
+        }
+        etag.push('"');
+        Some(etag)
+    } else {
+        None
+    };
+
+    let header = Header::from_bytes(&b"Content-Type"[..], reply.content_type.as_bytes())
+        .expect("static header is valid");
+    let mut resp = match etag {
+        Some(ref etag) if if_none_match.as_ref() == Some(etag) => {
+            HttpResponse::from_data(Vec::new()).with_status_code(304)
+        }
+        _ => HttpResponse::from_data(reply.body)
+            .with_status_code(reply.status)
+            .with_header(header),
+    };
+    if let Some(etag) = etag {
+        resp.add_header(
+            Header::from_bytes(&b"ETag"[..], etag.as_bytes()).expect("hex header is valid"),
+        );
+    }
+    let _ = req.respond(resp);
+}
+
+fn route(
+    state: &mut State,
+    method: &Method,
+    path: &str,
+    query: &HashMap<String, String>,
+    token: Option<&String>,
+    body: &[u8],
+) -> MockResponse {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let segments = match segments.split_first() {
+        Some((&"api", rest)) if rest.first() == Some(&"v1") => &rest[1..],
+        _ => return MockResponse::not_found(),
+    };
+
+    if let (&Method::Post, ["token"]) = (method, segments) {
+        return login(state, body);
+    }
+
+    let now = Utc::now();
+    match token.and_then(|t| state.tokens.get(t)) {
+        Some(expires) if *expires > now => (),
+        _ => return MockResponse::message(403, "Token authentication failed."),
+    }
+
+    let paging = Paging::new(state.pagination, query);
+    let dataset = &mut state.dataset;
+    match (method, segments) {
+        (&Method::Get, ["user"]) => user(dataset),
+        (&Method::Get, ["users"]) => users(dataset),
+        (&Method::Post, ["seen"]) => seen(dataset, body),
+        (&Method::Get, ["sources"]) => MockResponse::json(
+            200,
+            paging.apply("sources", dataset.sources.iter().map(source_json).collect()),
+        ),
+        _ if segments.len() >= 2 && segments[0] == "sources" => {
+            let source = match segments[1].parse::<SourceId>() {
+                Ok(s) => s,
+                Err(_) => return MockResponse::not_found(),
+            };
+            source_route(dataset, method, &source, &segments[2..], &paging, body)
+        }
+        _ => MockResponse::not_found(),
+    }
+}
+
+/// The page of a list requested by the client.
+struct Paging {
+    pagination: Option<Pagination>,
+    limit: Option<usize>,
+    offset: usize,
+}
+
+impl Paging {
+    fn new(pagination: Option<Pagination>, query: &HashMap<String, String>) -> Self {
+        let param = |name: &str| query.get(name).and_then(|v| v.parse::<usize>().ok());
+        let limit = param("limit").filter(|l| *l > 0);
+        let offset = match pagination {
+            Some(Pagination::Page) => (param("page").unwrap_or(1).max(1) - 1) * limit.unwrap_or(0),
+            Some(Pagination::Cursor) => param("cursor").unwrap_or(0),
+            Some(Pagination::Stuck) | None => 0,
+        };
+        Self {
+            pagination,
+            limit,
+            offset,
+        }
+    }
+
+    fn apply(&self, field: &str, items: Vec<Value>) -> Value {
+        let (pagination, limit) = match (self.pagination, self.limit) {
+            (Some(p), Some(l)) => (p, l),
+            _ => return json!({ field: items }),
+        };
+        let end = self.offset + limit;
+        let more = end < items.len();
+        let page: Vec<Value> = items.into_iter().skip(self.offset).take(limit).collect();
+        match pagination {
+            Pagination::Page => json!({
+                field: page,
+                "next_page": if more { Some(end / limit + 1) } else { None },
+            }),
+            Pagination::Cursor => json!({
+                field: page,
+                "next_cursor": if more { Some(end.to_string()) } else { None },
+            }),
+            Pagination::Stuck => json!({ field: page, "next_cursor": "stuck" }),
+        }
+    }
+}
+
+fn login(state: &mut State, body: &[u8]) -> MockResponse {
+    let creds: Value = match ::json::from_slice(body) {
+        Ok(v) => v,
+        Err(_) => return MockResponse::message(400, "Please send requests in valid JSON."),
+    };
+    let field = |name: &str| creds.get(name).and_then(Value::as_str).map(String::from);
+    let user = &mut state.dataset.user;
+    if field("username") != Some(user.username.clone())
+        || field("password") != Some(user.passphrase.clone())
+        || field("one_time_code") != Some(user.one_time_code.clone())
+    {
+        return MockResponse::message(403, "Token authentication failed.");
+    }
+
+    let token = Uuid::new_v4().simple().to_string();
+    let expires = Utc::now() + state.token_lifetime;
+    state.tokens.insert(token.clone(), expires);
+    user.last_login = Utc::now();
+    MockResponse::json(
+        200,
+        json!({
+            "token": token,
+            "expires": expires,
+            "journalist_uuid": user.uuid,
+        }),
+    )
+}
+
+fn seen(dataset: &mut Dataset, body: &[u8]) -> MockResponse {
+    let body: Value = match ::json::from_slice(body) {
+        Ok(v) => v,
+        Err(_) => return MockResponse::message(400, "Please send requests in valid JSON."),
+    };
+    let ids = |name: &str| -> Vec<String> {
+        body.get(name)
+            .and_then(Value::as_array)
+            .map(|a| {
+                a.iter()
+                    .filter_map(Value::as_str)
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let (files, messages, replies) = (ids("files"), ids("messages"), ids("replies"));
+    if files.is_empty() && messages.is_empty() && replies.is_empty() {
+        return MockResponse::message(400, "Please specify the resources to mark seen.");
+    }
+
+    // Check everything exists before marking anything
+    let is_message = |sub: &MockSubmission| {
+        sub.filename
+            .parse::<FileName>()
+            .map(|f| *f.kind() == FileKind::Message)
+            .unwrap_or(false)
+    };
+    let submissions = || dataset.sources.iter().flat_map(|s| s.submissions.iter());
+    let wanted = files
+        .iter()
+        .map(|f| (f, false))
+        .chain(messages.iter().map(|m| (m, true)));
+    for (uuid, message) in wanted {
+        if !submissions().any(|s| s.uuid.to_string() == *uuid && is_message(s) == message) {
+            return MockResponse::not_found();
+        }
+    }
+    for uuid in &replies {
+        let mut all = dataset.sources.iter().flat_map(|s| s.replies.iter());
+        if !all.any(|r| r.uuid.to_string() == *uuid) {
+            return MockResponse::not_found();
+        }
+    }
+
+    for sub in dataset
+        .sources
+        .iter_mut()
+        .flat_map(|s| s.submissions.iter_mut())
+    {
+        let uuid = sub.uuid.to_string();
+        if files.contains(&uuid) || messages.contains(&uuid) {
+            sub.is_read = true;
+        }
+    }
+    MockResponse::message(200, "resources marked seen")
+}
+
+fn user(dataset: &Dataset) -> MockResponse {
+    MockResponse::json(
+        200,
+        json!({
+            "user": {
+                "uuid": dataset.user.uuid,
+                "is_admin": false,
+                "last_login": dataset.user.last_login,
+                "username": dataset.user.username,
+            }
+        }),
+    )
+}
+
+fn users(dataset: &Dataset) -> MockResponse {
+    MockResponse::json(
+        200,
+        json!({
+            "users": [{
+                "uuid": dataset.user.uuid,
+                "username": dataset.user.username,
+                "first_name": null,
+                "last_name": null,
+            }]
+        }),
+    )
+}
+
+fn source_route(
+    dataset: &mut Dataset,
+    method: &Method,
+    source_id: &SourceId,
+    rest: &[&str],
+    paging: &Paging,
+    body: &[u8],
+) -> MockResponse {
+    if let (&Method::Delete, ["submissions"]) = (method, rest) {
+        return if dataset.remove_source(source_id) {
+            MockResponse::message(200, "Source and submissions deleted")
+        } else {
+            MockResponse::not_found()
+        };
+    }
+
+    if let (&Method::Post, ["reply"]) = (method, rest) {
+        let reply: Value = match ::json::from_slice(body) {
+            Ok(v) => v,
+            Err(_) => return MockResponse::message(400, "Please send requests in valid JSON."),
+        };
+        let uuid = match reply.get("uuid").and_then(Value::as_str) {
+            Some(u) => match u.parse() {
+                Ok(u) => u,
+                Err(_) => return MockResponse::message(400, "Invalid UUID"),
+            },
+            None => ReplyId::new(Uuid::new_v4()),
+        };
+        let content = match reply.get("reply").and_then(Value::as_str) {
+            Some(r) => r.as_bytes().to_vec(),
+            None => return MockResponse::message(400, "reply not found in request body"),
+        };
+        if dataset.has_reply(&uuid) {
+            return MockResponse::message(409, "That UUID is already in use.");
+        }
+        return match dataset.push_reply(source_id, uuid, content) {
+            Some(filename) => MockResponse::json(
+                201,
+                json!({
+                    "message": "Your reply has been stored",
+                    "uuid": uuid.to_string(),
+                    "filename": filename,
+                }),
+            ),
+            None => MockResponse::not_found(),
+        };
+    }
+
+    let journalist = dataset.user.clone();
+    let source = match dataset.source_mut(source_id) {
+        Some(s) => s,
+        None => return MockResponse::not_found(),
+    };
+
+    match (method, rest) {
+        (&Method::Get, []) => MockResponse::json(200, source_json(source)),
+        (&Method::Post, ["star"]) => {
+            source.is_starred = true;
+            MockResponse::message(201, "Star added")
+        }
+        (&Method::Delete, ["star"]) => {
+            source.is_starred = false;
+            MockResponse::message(200, "Star removed")
+        }
+        (&Method::Get, ["submissions"]) => {
+            let subs: Vec<_> = source
+                .submissions
+                .iter()
+                .map(|s| submission_json(source, s))
+                .collect();
+            MockResponse::json(200, paging.apply("submissions", subs))
+        }
+        (&Method::Get, ["submissions", id]) => match find_submission(source, id) {
+            Some(idx) => MockResponse::json(200, submission_json(source, &source.submissions[idx])),
+            None => MockResponse::not_found(),
+        },
+        (&Method::Delete, ["submissions", id]) => match find_submission(source, id) {
+            Some(idx) => {
+                source.submissions.remove(idx);
+                MockResponse::message(200, "Submission deleted")
+            }
+            None => MockResponse::not_found(),
+        },
+        (&Method::Get, ["submissions", id, "download"]) => match find_submission(source, id) {
+            Some(idx) => {
+                source.submissions[idx].is_read = true;
+                MockResponse::file(source.submissions[idx].content.clone())
+            }
+            None => MockResponse::not_found(),
+        },
+        (&Method::Get, ["replies"]) => {
+            let replies: Vec<_> = source
+                .replies
+                .iter()
+                .map(|r| reply_json(&journalist, r))
+                .collect();
+            MockResponse::json(200, json!({ "replies": replies }))
+        }
+        (&Method::Get, ["replies", id]) => match find_reply(source, id) {
+            Some(reply) => MockResponse::json(200, reply_json(&journalist, reply)),
+            None => MockResponse::not_found(),
+        },
+        (&Method::Get, ["replies", id, "download"]) => match find_reply(source, id) {
+            Some(reply) => MockResponse::file(reply.content.clone()),
+            None => MockResponse::not_found(),
+        },
+        _ => MockResponse::not_found(),
+    }
+}
+
+fn find_submission(source: &MockSource, id: &str) -> Option<usize> {
+    source
+        .submissions
+        .iter()
+        .position(|s| s.uuid.to_string() == id)
+}
+
+fn find_reply<'a>(source: &'a MockSource, id: &str) -> Option<&'a MockReply> {
+    source.replies.iter().find(|r| r.uuid.to_string() == id)
+}
+
+fn source_json(source: &MockSource) -> Value {
+    let count = |kind: FileKind| {
+        source
+            .submissions
+            .iter()
+            .filter(|s| {
+                s.filename
+                    .parse::<FileName>()
+                    .map(|f| *f.kind() == kind)
+                    .unwrap_or(false)
+            })
+            .count()
+    };
+    json!({
+        "uuid": source.uuid,
+        "flagged": source.is_flagged,
+        "is_starred": source.is_starred,
+        "last_updated": source.last_updated,
+        "interaction_count": source.interaction_count,
+        "journalist_designation": source.journalist_designation,
+        "number_of_documents": count(FileKind::Document),
+        "number_of_messages": count(FileKind::Message),
+        "public_key": source.public_key,
+    })
+}
+
+fn submission_json(source: &MockSource, submission: &MockSubmission) -> Value {
+    json!({
+        "uuid": submission.uuid,
+        "source_uuid": source.uuid,
+        "filename": submission.filename,
+        "is_read": submission.is_read,
+        "size": submission.content.len(),
+        "download_url": format!(
+            "/api/v1/sources/{}/submissions/{}/download",
+            source.uuid, submission.uuid
+        ),
+        "last_updated": submission.last_updated,
+    })
+}
+
+fn reply_json(journalist: &MockUser, reply: &MockReply) -> Value {
+    json!({
+        "uuid": reply.uuid,
+        "filename": reply.filename,
+        "is_deleted_by_source": false,
+        "journalist_username": journalist.username,
+        "journalist_uuid": journalist.uuid,
+        "size": reply.content.len(),
+    })
+}
@@ -0,0 +1,89 @@
+//! One time password generation (RFC 4226 HOTP and RFC 6238 TOTP).
+
+use base32::{self, Alphabet};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::Result;
+use error::ErrorKind;
+
+/// The number of digits in codes accepted by SecureDrop.
+pub(crate) const DIGITS: u32 = 6;
+
+/// The length of a TOTP time step in seconds.
+pub(crate) const TIME_STEP: u64 = 30;
+
+/// Decode a base32 shared secret as displayed to users (case insensitive, with optional spaces and
+/// padding).
+pub(crate) fn decode_secret(secret: &str) -> Result<Vec<u8>> {
+    let normalized: String = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    match base32::decode(Alphabet::RFC4648 { padding: false }, &normalized) {
+        Some(ref key) if !key.is_empty() => Ok(key.clone()),
+        _ => Err(ErrorKind::ClientError("Invalid OTP secret".into()).into()),
+    }
+}
+
+/// Generate the HOTP code for `counter`.
+pub(crate) fn hotp(key: &[u8], counter: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = (u32::from(hash[offset]) & 0x7f) << 24
+        | u32::from(hash[offset + 1]) << 16
+        | u32::from(hash[offset + 2]) << 8
+        | u32::from(hash[offset + 3]);
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// The TOTP time step containing `unix_time`.
+pub(crate) fn time_step(unix_time: u64) -> u64 {
+    unix_time / TIME_STEP
+}
+
+/// Seconds since the Unix epoch.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: &[u8] = b"12345678901234567890";
+
+    // Test vectors from RFC 4226 appendix D and RFC 6238 appendix B (truncated to 6 digits)
+    #[test]
+    fn rfc_test_vectors() {
+        let hotp_codes = ["755224", "287082", "359152", "969429", "338314"];
+        for (counter, code) in hotp_codes.iter().enumerate() {
+            assert_eq!(hotp(KEY, counter as u64), *code);
+        }
+        assert_eq!(hotp(KEY, time_step(59)), "287082");
+        assert_eq!(hotp(KEY, time_step(1_111_111_109)), "081804");
+        assert_eq!(hotp(KEY, time_step(2_000_000_000)), "279037");
+    }
+
+    #[test]
+    fn decode_secrets() {
+        assert_eq!(
+            decode_secret("gezd gnbv gy3t qojq gezd gnbv gy3t qojq").unwrap(),
+            KEY
+        );
+        assert!(decode_secret("not base32!").is_err());
+        assert!(decode_secret("").is_err());
+    }
+}
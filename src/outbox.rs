@@ -0,0 +1,358 @@
+//! Queue changes while offline and send them once the server is reachable again.
+//!
+//! An `Outbox` records pending operations (starring, replying, deleting) durably, in the order
+//! they were made. Queuing an operation also applies it optimistically to a sync `Store` so the
+//! application can show the result straight away. `Outbox::replay` then sends the operations in
+//! order, stopping at the first one that fails because the server cannot be reached.
+//!
+//! Operations the server rejects (e.g., a reply to a source that was deleted in the meantime) are
+//! reported as conflicts and dropped from the queue. The next sync brings the store back in line
+//! with the server, so replay the outbox before syncing.
+//!
+//! ```no_run
+//! # extern crate securedrop_api;
+//! use securedrop_api::auth::UserPassTotp;
+//! use securedrop_api::outbox::{Operation, Outbox};
+//! use securedrop_api::sync::{MemoryStore, Store, Syncer};
+//! use securedrop_api::Client;
+//!
+//! # fn main() {
+//! # let creds = UserPassTotp::new("journalist".into(), "pass".into(), "123456".into());
+//! let client = Client::new("http://localhost:8081".parse().unwrap(), creds, None).unwrap();
+//! let mut syncer = Syncer::new(MemoryStore::new());
+//! syncer.sync(&client).unwrap();
+//!
+//! let mut outbox = Outbox::open("outbox.json").unwrap();
+//! let source = *syncer.store().sources().unwrap()[0].uuid();
+//! outbox.queue(syncer.store_mut(), Operation::Star { source }).unwrap();
+//!
+//! // Later, once the network is back
+//! let replay = outbox.replay(&client).unwrap();
+//! for conflict in replay.conflicts() {
+//!     println!("Not sent: {}", conflict.error());
+//! }
+//! syncer.sync(&client).unwrap();
+//! # }
+//! ```
+
+use chrono::{DateTime, Utc};
+use json;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use super::Result;
+use auth::write_private;
+use client::Client;
+use data::{Reply, SourceId, SubmissionId};
+use error::{Error, ErrorKind};
+use sync::{ChangeSet, Store};
+
+/// A change to make on the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Operation {
+    /// Add a star to a source.
+    Star {
+        /// The source to star.
+        source: SourceId,
+    },
+    /// Remove a star from a source.
+    Unstar {
+        /// The source to unstar.
+        source: SourceId,
+    },
+    /// Send a pre-encrypted reply to a source. Replies are not added to the store until they
+    /// have been sent and synced, so use `Outbox::pending` to show unsent replies.
+    Reply {
+        /// The source to reply to.
+        source: SourceId,
+        /// The encrypted reply.
+        reply: Reply,
+    },
+    /// Delete one submission.
+    DeleteSubmission {
+        /// The source the submission belongs to.
+        source: SourceId,
+        /// The submission to delete.
+        submission: SubmissionId,
+    },
+    /// Delete a source and all of its submissions.
+    DeleteSource {
+        /// The source to delete.
+        source: SourceId,
+    },
+}
+
+impl Operation {
+    /// The source the operation affects.
+    pub fn source(&self) -> &SourceId {
+        match *self {
+            Operation::Star { ref source }
+            | Operation::Unstar { ref source }
+            | Operation::Reply { ref source, .. }
+            | Operation::DeleteSubmission { ref source, .. }
+            | Operation::DeleteSource { ref source } => source,
+        }
+    }
+
+    fn send(&self, client: &Client) -> Result<()> {
+        match *self {
+            Operation::Star { ref source } => client.star_source(source).map(|_| ()),
+            Operation::Unstar { ref source } => client.unstar_source(source).map(|_| ()),
+            Operation::Reply {
+                ref source,
+                ref reply,
+            } => match client.reply_to_source(source, reply) {
+                Ok(_) => Ok(()),
+                // An earlier attempt may have been stored even though we never saw the response
+                Err(ref e)
+                    if e.status() == Some(409) && client.reply_exists(source, reply.uuid())? =>
+                {
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            },
+            Operation::DeleteSubmission {
+                ref source,
+                ref submission,
+            } => client
+                .delete_source_submission(source, submission)
+                .map(|_| ()),
+            Operation::DeleteSource { ref source } => client.delete_submissions(source).map(|_| ()),
+        }
+    }
+
+    /// The changes to the store that the operation will cause once it is sent.
+    fn changes<S: Store + ?Sized>(&self, store: &S) -> Result<ChangeSet> {
+        let mut changes = ChangeSet::default();
+        match *self {
+            Operation::Star { ref source } => set_starred(&mut changes, store, source, true)?,
+            Operation::Unstar { ref source } => set_starred(&mut changes, store, source, false)?,
+            Operation::Reply { .. } => (),
+            Operation::DeleteSubmission {
+                ref source,
+                ref submission,
+            } => {
+                if store
+                    .submissions(source)?
+                    .iter()
+                    .any(|s| s.uuid() == submission)
+                {
+                    changes.submissions.deleted.push(*submission);
+                }
+            }
+            Operation::DeleteSource { ref source } => {
+                if store.sources()?.iter().any(|s| s.uuid() == source) {
+                    changes.sources.deleted.push(*source);
+                    let submissions = store.submissions(source)?;
+                    changes.submissions.deleted = submissions.iter().map(|s| *s.uuid()).collect();
+                    let replies = store.replies(source)?;
+                    changes.replies.deleted = replies.iter().map(|r| *r.uuid()).collect();
+                }
+            }
+        }
+        Ok(changes)
+    }
+}
+
+fn set_starred<S: Store + ?Sized>(
+    changes: &mut ChangeSet,
+    store: &S,
+    source: &SourceId,
+    starred: bool,
+) -> Result<()> {
+    if let Some(mut stored) = store.sources()?.into_iter().find(|s| s.uuid() == source) {
+        stored.set_starred(starred);
+        changes.sources.changed.push(stored);
+    }
+    Ok(())
+}
+
+/// An operation waiting to be sent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pending {
+    id: Uuid,
+    operation: Operation,
+    queued_at: DateTime<Utc>,
+}
+
+impl Pending {
+    /// A unique identifier for this entry in the queue.
+    pub fn id(&self) -> &Uuid {
+        &self.id
+    }
+
+    /// The operation to send.
+    pub fn operation(&self) -> &Operation {
+        &self.operation
+    }
+
+    /// When the operation was queued.
+    pub fn queued_at(&self) -> &DateTime<Utc> {
+        &self.queued_at
+    }
+}
+
+/// An operation the server rejected.
+#[derive(Debug)]
+pub struct Conflict {
+    pending: Pending,
+    error: Error,
+}
+
+impl Conflict {
+    /// The rejected operation.
+    pub fn pending(&self) -> &Pending {
+        &self.pending
+    }
+
+    /// Why the server rejected it.
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+}
+
+/// The outcome of replaying an `Outbox`.
+#[derive(Debug, Default)]
+pub struct Replay {
+    sent: Vec<Pending>,
+    conflicts: Vec<Conflict>,
+    stopped_by: Option<Error>,
+}
+
+impl Replay {
+    /// Operations that were sent successfully.
+    pub fn sent(&self) -> &[Pending] {
+        &self.sent
+    }
+
+    /// Operations the server rejected. These have been removed from the outbox.
+    pub fn conflicts(&self) -> &[Conflict] {
+        &self.conflicts
+    }
+
+    /// The error (e.g., a network error) that stopped the replay before the outbox was empty. The
+    /// remaining operations are kept for the next replay.
+    pub fn stopped_by(&self) -> Option<&Error> {
+        self.stopped_by.as_ref()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Queue {
+    version: u32,
+    pending: Vec<Pending>,
+}
+
+/// A queue of operations waiting to be sent.
+#[derive(Debug, Default)]
+pub struct Outbox {
+    path: Option<PathBuf>,
+    pending: Vec<Pending>,
+}
+
+impl Outbox {
+    /// Create an outbox that only lives in memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open (or create) an outbox that is saved to `path` after every change.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let pending = match File::open(path) {
+            Ok(file) => {
+                let queue: Queue = json::from_reader(BufReader::new(file))
+                    .map_err(|e| ErrorKind::IO(format!("Invalid outbox: {}", e)))?;
+                queue.pending
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(ErrorKind::IO(e.to_string()).into()),
+        };
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            pending,
+        })
+    }
+
+    /// Operations waiting to be sent, oldest first.
+    pub fn pending(&self) -> &[Pending] {
+        &self.pending
+    }
+
+    /// Whether there is nothing to send.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Add an operation to the queue and apply its effects to `store`. The queue is saved before
+    /// `store` is changed, and the operation is removed again if the changes cannot be applied.
+    pub fn queue<S: Store + ?Sized>(
+        &mut self,
+        store: &mut S,
+        operation: Operation,
+    ) -> Result<&Pending> {
+        let changes = operation.changes(store)?;
+        self.pending.push(Pending {
+            id: Uuid::new_v4(),
+            operation,
+            queued_at: Utc::now(),
+        });
+        if let Err(e) = self.save() {
+            self.pending.pop();
+            return Err(e);
+        }
+        if !changes.is_empty() {
+            if let Err(e) = store.apply(&changes) {
+                self.pending.pop();
+                self.save()?;
+                return Err(e);
+            }
+        }
+        Ok(&self.pending[self.pending.len() - 1])
+    }
+
+    /// Send the queued operations in order. Each operation is removed from the queue once the
+    /// server has accepted it, or rejected it because what it refers to is gone (`404 Not Found`)
+    /// or conflicts with the server's state (`409 Conflict`). Any other error (e.g., `403
+    /// Forbidden` once the client's token has expired) stops the replay and is reported by
+    /// `Replay::stopped_by`, keeping the operation queued so that the replay can be retried after
+    /// reauthorizing. An `Err` is only returned if the queue cannot be saved.
+    pub fn replay(&mut self, client: &Client) -> Result<Replay> {
+        let mut replay = Replay::default();
+        while !self.pending.is_empty() {
+            match self.pending[0].operation.send(client) {
+                Ok(()) => replay.sent.push(self.pending.remove(0)),
+                Err(error) => {
+                    if error.status() == Some(404) || error.status() == Some(409) {
+                        replay.conflicts.push(Conflict {
+                            pending: self.pending.remove(0),
+                            error,
+                        });
+                    } else {
+                        replay.stopped_by = Some(error);
+                        break;
+                    }
+                }
+            }
+            self.save()?;
+        }
+        Ok(replay)
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let queue = Queue {
+            version: 1,
+            pending: self.pending.clone(),
+        };
+        let bytes =
+            json::to_vec_pretty(&queue).map_err(|e| ErrorKind::ProgrammingError(e.to_string()))?;
+        write_private(path, &bytes)
+    }
+}
@@ -0,0 +1,134 @@
+//! Lazily page through long lists.
+//!
+//! **Experimental:** no released SecureDrop server paginates its API, so the parameters below are
+//! this crate's proposal (implemented by the `mock` server) rather than a published spec, and may
+//! change once the server supports pagination. Against current servers, `Pages` simply yields the
+//! whole list as one page.
+//!
+//! Servers that paginate list endpoints accept a `limit` query parameter and include either a
+//! `next_cursor` (sent back as `cursor`) or a `next_page` (sent back as `page`) in the response
+//! until the last page. Older servers ignore `limit` and return the whole list in one response,
+//! which `Pages` yields as a single page, so callers do not need to know which kind of server they
+//! are talking to.
+//!
+//! ```no_run
+//! # extern crate securedrop_api;
+//! use securedrop_api::auth::UserPassTotp;
+//! use securedrop_api::Client;
+//!
+//! # fn main() {
+//! # let creds = UserPassTotp::new("journalist".into(), "pass".into(), "123456".into());
+//! let client = Client::new("http://localhost:8081".parse().unwrap(), creds, None).unwrap();
+//! for source in client.sources_paged(100) {
+//!     println!("{}", source.unwrap().journalist_designation());
+//! }
+//! # }
+//! ```
+
+use json::{self, Map, Value};
+use serde::de::DeserializeOwned;
+use std::collections::{HashSet, VecDeque};
+
+use super::Result;
+use client::Client;
+use error::{Error, ErrorKind};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Next {
+    First,
+    Cursor(String),
+    Page(u64),
+    Done,
+}
+
+#[derive(Debug, Deserialize)]
+struct PageResponse {
+    next_cursor: Option<String>,
+    next_page: Option<u64>,
+    #[serde(flatten)]
+    rest: Map<String, Value>,
+}
+
+/// An iterator over the items of a list endpoint that fetches one page at a time. Created with
+/// methods like `Client::sources_paged`.
+///
+/// Iteration stops after the first error. A server that sends a cursor or page number it already
+/// sent is treated as a `ServerError` instead of being followed forever.
+pub struct Pages<'a, T> {
+    client: &'a Client,
+    path: String,
+    field: &'static str,
+    limit: u32,
+    next: Next,
+    seen: HashSet<Next>,
+    buffer: VecDeque<T>,
+}
+
+impl<'a, T: DeserializeOwned> Pages<'a, T> {
+    pub(crate) fn new(client: &'a Client, path: String, field: &'static str, limit: u32) -> Self {
+        Self {
+            client,
+            path,
+            field,
+            limit: limit.max(1),
+            next: Next::First,
+            seen: HashSet::new(),
+            buffer: VecDeque::new(),
+        }
+    }
+
+    /// Fetch the next page. Returns `None` once every page has been fetched. Any items of the
+    /// current page not yet returned by the iterator are skipped.
+    pub fn next_page(&mut self) -> Option<Result<Vec<T>>> {
+        self.buffer.clear();
+        let mut query = vec![("limit", self.limit.to_string())];
+        match self.next {
+            Next::First => (),
+            Next::Cursor(ref cursor) => query.push(("cursor", cursor.clone())),
+            Next::Page(page) => query.push(("page", page.to_string())),
+            Next::Done => return None,
+        }
+
+        let result = self.fetch(&query);
+        if result.is_err() {
+            self.next = Next::Done;
+        }
+        Some(result)
+    }
+
+    fn fetch(&mut self, query: &[(&str, String)]) -> Result<Vec<T>> {
+        let (status, mut resp): (u16, PageResponse) =
+            self.client.get_with_query(&self.path, query)?;
+        // A malformed page or one that does not advance is the server's fault
+        let server_error = || Error::new(ErrorKind::ServerError).with_status(status);
+        let items = resp.rest.remove(self.field).ok_or_else(server_error)?;
+        let items = json::from_value(items).map_err(|_| server_error())?;
+
+        let next = match (resp.next_cursor, resp.next_page) {
+            (Some(cursor), _) => Next::Cursor(cursor),
+            (None, Some(page)) => Next::Page(page),
+            // Either the last page or an older server that returned everything
+            (None, None) => Next::Done,
+        };
+        if next != Next::Done && !self.seen.insert(next.clone()) {
+            return Err(server_error());
+        }
+        self.next = next;
+        Ok(items)
+    }
+}
+
+impl<'a, T: DeserializeOwned> Iterator for Pages<'a, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Loop in case the server returns an empty page that is not the last
+        while self.buffer.is_empty() {
+            match self.next_page()? {
+                Ok(items) => self.buffer.extend(items),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.buffer.pop_front().map(Ok)
+    }
+}
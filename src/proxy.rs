@@ -0,0 +1,338 @@
+//! A `Transport` that relays requests through an external proxy process.
+//!
+//! On workstations where the client runs without network access, HTTP requests are handed to a
+//! separate proxy (e.g., over `qrexec`) as a JSON description on stdin, and the proxy prints a JSON
+//! description of the response on stdout:
+//!
+//! ```text
+//! > {"method": "GET", "path_query": "/api/v1/sources", "headers": {...}, "body": null, "timeout": 20}
+//! < {"status": 200, "headers": {"Content-Type": "application/json"}, "body": "{\"sources\": []}"}
+//! ```
+//!
+//! Successful responses that are not JSON (i.e., file downloads) are not sent inline. Instead, the
+//! proxy writes the body to a file in the incoming directory and returns `{"filename": "..."}` as
+//! the body, where the filename is relative to that directory. The file is streamed back to the
+//! caller and removed once the body is dropped.
+//!
+//! The proxy is not trusted with access to the rest of the filesystem: filenames that are
+//! absolute, contain `..`, or otherwise resolve outside the incoming directory are rejected.
+//!
+//! ```no_run
+//! # extern crate securedrop_api;
+//! use securedrop_api::auth::UserPassTotp;
+//! use securedrop_api::proxy::ProxyTransport;
+//! use securedrop_api::Client;
+//!
+//! # fn main() {
+//! let creds = UserPassTotp::new("journalist".into(), "pass".into(), "123456".into());
+//! let transport = ProxyTransport::new(
+//!     "/usr/lib/qubes/qrexec-client-vm",
+//!     "/home/user/QubesIncoming/sd-proxy",
+//! ).arg("sd-proxy")
+//! .arg("securedrop.Proxy");
+//! let url = "http://localhost:8081".parse().unwrap();
+//! let client = Client::with_transport(url, transport, creds, None).unwrap();
+//! # }
+//! ```
+
+use json;
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str;
+
+use super::Result;
+use error::{Error, ErrorKind};
+use transport::{Method, Request, Response, Transport};
+
+#[derive(Debug, Serialize)]
+struct ProxyRequest<'a> {
+    method: Method,
+    path_query: String,
+    headers: BTreeMap<&'a str, &'a str>,
+    body: Option<&'a str>,
+    timeout: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProxyResponse {
+    status: u16,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    body: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DownloadedFile {
+    filename: PathBuf,
+}
+
+/// A `Transport` that spawns a proxy command for each request and exchanges JSON with it over
+/// stdin and stdout.
+#[derive(Debug, Clone)]
+pub struct ProxyTransport {
+    program: OsString,
+    args: Vec<OsString>,
+    incoming_dir: PathBuf,
+}
+
+impl ProxyTransport {
+    /// Create a transport that runs `program` for each request. Downloaded files are read from
+    /// `incoming_dir` (e.g., the directory `qvm-move` delivers files to).
+    pub fn new<S, P>(program: S, incoming_dir: P) -> Self
+    where
+        S: AsRef<OsStr>,
+        P: AsRef<Path>,
+    {
+        Self {
+            program: program.as_ref().to_os_string(),
+            args: Vec::new(),
+            incoming_dir: incoming_dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Add an argument to pass to the proxy command.
+    pub fn arg<S: AsRef<OsStr>>(mut self, arg: S) -> Self {
+        self.args.push(arg.as_ref().to_os_string());
+        self
+    }
+
+    fn envelope(&self, request: &Request) -> Result<Vec<u8>> {
+        let url = request.url();
+        let path_query = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let body = match request.body() {
+            Some(body) => Some(str::from_utf8(body).map_err(|_| {
+                ErrorKind::ProgrammingError("Proxy request bodies must be UTF-8".into())
+            })?),
+            None => None,
+        };
+        let envelope = ProxyRequest {
+            method: request.method(),
+            path_query,
+            headers: request
+                .headers()
+                .iter()
+                .map(|&(ref n, ref v)| (n.as_str(), v.as_str()))
+                .collect(),
+            body,
+            // Round up so that a sub-second timeout does not become "no time at all"
+            timeout: request
+                .timeout()
+                .map(|t| t.as_secs() + if t.subsec_nanos() > 0 { 1 } else { 0 }),
+        };
+        json::to_vec(&envelope).map_err(|e| ErrorKind::ProgrammingError(e.to_string()).into())
+    }
+
+    fn run(&self, input: &[u8]) -> Result<Vec<u8>> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| ErrorKind::IO(format!("Failed to start proxy: {}", e)))?;
+        {
+            // A proxy that exits without reading its input is reported by its exit status below.
+            let mut stdin = child.stdin.take().expect("stdin is piped");
+            match stdin.write_all(input) {
+                Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => (),
+                x => x.map_err(io_error)?,
+            }
+        }
+        let output = child.wait_with_output().map_err(io_error)?;
+        if !output.status.success() {
+            return Err(ErrorKind::NetworkError.into());
+        }
+        Ok(output.stdout)
+    }
+
+    fn downloaded_file(&self, body: &str) -> Result<PathBuf> {
+        let file: DownloadedFile = json::from_str(body)
+            .map_err(|e| ErrorKind::IO(format!("Invalid proxy response: {}", e)))?;
+        let invalid = || {
+            let msg = format!("Invalid proxy filename: {}", file.filename.display());
+            Error::new(ErrorKind::IO(msg))
+        };
+
+        // Only accept plain relative paths (e.g., `dir/file` but not `/file` or `../file`)
+        if file.filename.components().next().is_none() {
+            return Err(invalid());
+        }
+        for component in file.filename.components() {
+            match component {
+                Component::Normal(_) => (),
+                _ => return Err(invalid()),
+            }
+        }
+
+        // Symlinks may still point elsewhere, so check where the path really leads
+        let dir = self.incoming_dir.canonicalize().map_err(io_error)?;
+        let path = dir.join(&file.filename).canonicalize().map_err(io_error)?;
+        if !path.starts_with(&dir) || path == dir {
+            return Err(invalid());
+        }
+        Ok(path)
+    }
+}
+
+impl Transport for ProxyTransport {
+    fn send(&self, request: &Request) -> Result<Response> {
+        let stdout = self.run(&self.envelope(request)?)?;
+        let resp: ProxyResponse = json::from_slice(&stdout)
+            .map_err(|e| ErrorKind::IO(format!("Invalid proxy response: {}", e)))?;
+        let is_json = resp
+            .headers
+            .iter()
+            .any(|(n, v)| n.eq_ignore_ascii_case("Content-Type") && v.contains("json"));
+        let headers = resp.headers.into_iter().collect();
+
+        // Only successful responses with a body are downloads (e.g., not `204 No Content` or
+        // `304 Not Modified`)
+        let is_success = resp.status >= 200 && resp.status < 300;
+        if is_json || !is_success || resp.body.is_empty() {
+            return Ok(Response::from_bytes(
+                resp.status,
+                headers,
+                resp.body.into_bytes(),
+            ));
+        }
+
+        let path = self.downloaded_file(&resp.body)?;
+        let file = File::open(&path).map_err(io_error)?;
+        Ok(Response::new(resp.status, headers, TempFile { path, file }))
+    }
+}
+
+fn io_error(e: io::Error) -> Error {
+    ErrorKind::IO(e.to_string()).into()
+}
+
+/// A downloaded file that is removed once it has been read (or dropped).
+struct TempFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl Read for TempFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use std::env;
+    use std::time::Duration;
+
+    fn request() -> Request {
+        let mut req = Request::new(
+            Method::Get,
+            "http://localhost/api/v1/sources?x=1".parse().unwrap(),
+        );
+        req.set_header("Accept", "application/json");
+        req.set_timeout(Some(Duration::from_secs(20)));
+        req
+    }
+
+    /// A proxy that replies with `response` after reading the request.
+    fn proxy(response: &str) -> ProxyTransport {
+        ProxyTransport::new("sh", env::temp_dir())
+            .arg("-c")
+            .arg(format!("cat > /dev/null; echo '{}'", response))
+    }
+
+    #[test]
+    fn envelope() {
+        let transport = ProxyTransport::new("true", env::temp_dir());
+        let envelope: json::Value =
+            json::from_slice(&transport.envelope(&request()).unwrap()).unwrap();
+        let expected: json::Value = json::from_str(
+            r#"{"method": "GET", "path_query": "/api/v1/sources?x=1",
+                "headers": {"Accept": "application/json"}, "body": null, "timeout": 20}"#,
+        )
+        .unwrap();
+        assert_eq!(envelope, expected);
+
+        let mut req = request();
+        req.set_timeout(Some(Duration::from_millis(1500)));
+        let envelope: json::Value = json::from_slice(&transport.envelope(&req).unwrap()).unwrap();
+        assert_eq!(envelope["timeout"], 2);
+        req.set_timeout(Some(Duration::from_millis(100)));
+        let envelope: json::Value = json::from_slice(&transport.envelope(&req).unwrap()).unwrap();
+        assert_eq!(envelope["timeout"], 1);
+    }
+
+    #[test]
+    fn json_response() {
+        let transport = proxy(
+            r#"{"status": 200, "headers": {"Content-Type": "application/json"}, "body": "{}"}"#,
+        );
+        let resp = transport.send(&request()).unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.read_body().unwrap(), b"{}");
+    }
+
+    #[test]
+    fn bodiless_response() {
+        for status in &[204, 304] {
+            let transport = proxy(&format!(
+                r#"{{"status": {}, "headers": {{}}, "body": ""}}"#,
+                status
+            ));
+            let resp = transport.send(&request()).unwrap();
+            assert_eq!(resp.status(), *status);
+            assert!(resp.read_body().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn file_response() {
+        let dir = env::temp_dir();
+        let name = format!("securedrop-proxy-test-{}", ::std::process::id());
+        let transport = ProxyTransport::new("sh", &dir).arg("-c").arg(format!(
+            r#"cat > /dev/null; printf data > '{}'; echo '{{"status": 200, "headers": {{"Content-Type": "application/pgp-encrypted"}}, "body": "{{\"filename\": \"{}\"}}"}}'"#,
+            dir.join(&name).display(),
+            name
+        ));
+        let resp = transport.send(&request()).unwrap();
+        assert_eq!(resp.read_body().unwrap(), b"data");
+        assert!(!dir.join(&name).exists());
+    }
+
+    #[test]
+    fn file_outside_incoming_dir() {
+        for filename in &["/etc/passwd", "../x", "", "."] {
+            let transport = proxy(&format!(
+                r#"{{"status": 200, "headers": {{"Content-Type": "application/pgp-encrypted"}}, "body": "{{\"filename\": \"{}\"}}"}}"#,
+                filename
+            ));
+            let err = transport.send(&request()).unwrap_err();
+            match *err.kind() {
+                ErrorKind::IO(_) => (),
+                ref kind => panic!("Unexpected error for {:?}: {:?}", filename, kind),
+            }
+        }
+        assert!(Path::new("/etc/passwd").exists());
+    }
+
+    #[test]
+    fn proxy_failure() {
+        let transport = ProxyTransport::new("false", env::temp_dir());
+        let err = transport.send(&request()).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::NetworkError);
+    }
+}
@@ -0,0 +1,292 @@
+//! Filter and sort sources on the client.
+//!
+//! ```no_run
+//! # extern crate chrono;
+//! # extern crate securedrop_api;
+//! use chrono::{Duration, Utc};
+//! use securedrop_api::auth::UserPassTotp;
+//! use securedrop_api::query::{Order, SortKey};
+//! use securedrop_api::Client;
+//!
+//! # fn main() {
+//! # let creds = UserPassTotp::new("journalist".into(), "pass".into(), "123456".into());
+//! let client = Client::new("http://localhost:8081".parse().unwrap(), creds, None).unwrap();
+//! let sources = client.sources().unwrap();
+//! let recent = sources
+//!     .query()
+//!     .starred()
+//!     .updated_since(Utc::now() - Duration::days(7))
+//!     .sort_by(SortKey::LastUpdated, Order::Descending)
+//!     .run();
+//! for source in recent {
+//!     println!("{}", source.journalist_designation());
+//! }
+//! # }
+//! ```
+
+use chrono::{DateTime, Utc};
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+
+use data::{Source, SourceId, Submission};
+
+/// What to sort sources by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// `Source::last_updated`.
+    LastUpdated,
+    /// `Source::interaction_count`.
+    InteractionCount,
+    /// `Source::journalist_designation`.
+    Designation,
+}
+
+/// The direction to sort in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    /// Smallest (or oldest) first.
+    Ascending,
+    /// Largest (or newest) first.
+    Descending,
+}
+
+/// A filter and sort order over a list of sources. Created with `Sources::query` or
+/// `SourceQuery::new`. All filters must match for a source to be included.
+#[derive(Debug, Clone)]
+pub struct SourceQuery<'a> {
+    sources: &'a [Source],
+    starred: bool,
+    unread: Option<BTreeSet<SourceId>>,
+    updated_since: Option<DateTime<Utc>>,
+    designation: Option<String>,
+    min_documents: Option<u32>,
+    sort: Option<(SortKey, Order)>,
+}
+
+impl<'a> SourceQuery<'a> {
+    /// Query the given sources (e.g., those held in a sync `Store`).
+    pub fn new(sources: &'a [Source]) -> Self {
+        Self {
+            sources,
+            starred: false,
+            unread: None,
+            updated_since: None,
+            designation: None,
+            min_documents: None,
+            sort: None,
+        }
+    }
+
+    /// Only include starred sources.
+    pub fn starred(mut self) -> Self {
+        self.starred = true;
+        self
+    }
+
+    /// Only include sources with at least one unread submission among `submissions`. The sources
+    /// endpoint does not report unread submissions, so pass the submissions fetched for the
+    /// sources (or held in a sync `Store`, refreshed with `sync::Syncer::refresh` since a plain
+    /// sync does not see submissions being marked seen).
+    pub fn has_unread<'s, I>(mut self, submissions: I) -> Self
+    where
+        I: IntoIterator<Item = &'s Submission>,
+    {
+        self.unread = Some(
+            submissions
+                .into_iter()
+                .filter(|s| !s.is_read())
+                .map(|s| *s.source_uuid())
+                .collect(),
+        );
+        self
+    }
+
+    /// Only include sources updated at or after `since`.
+    pub fn updated_since(mut self, since: DateTime<Utc>) -> Self {
+        self.updated_since = Some(since);
+        self
+    }
+
+    /// Only include sources whose designation contains `text`, ignoring case.
+    pub fn designation_contains<S: Into<String>>(mut self, text: S) -> Self {
+        self.designation = Some(text.into().to_lowercase());
+        self
+    }
+
+    /// Only include sources that have submitted at least `count` documents.
+    pub fn min_documents(mut self, count: u32) -> Self {
+        self.min_documents = Some(count);
+        self
+    }
+
+    /// Sort the results. Without this, sources are returned in their original order. Sources
+    /// that compare equal keep their original order.
+    pub fn sort_by(mut self, key: SortKey, order: Order) -> Self {
+        self.sort = Some((key, order));
+        self
+    }
+
+    /// Whether a source matches the filters.
+    pub fn matches(&self, source: &Source) -> bool {
+        if self.starred && !source.is_starred() {
+            return false;
+        }
+        if let Some(ref unread) = self.unread {
+            if !unread.contains(source.uuid()) {
+                return false;
+            }
+        }
+        if let Some(since) = self.updated_since {
+            if *source.last_updated() < since {
+                return false;
+            }
+        }
+        if let Some(ref text) = self.designation {
+            if !source
+                .journalist_designation()
+                .to_lowercase()
+                .contains(text)
+            {
+                return false;
+            }
+        }
+        if let Some(min) = self.min_documents {
+            if source.number_of_documents().map_or(true, |n| n < min) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Run the query.
+    pub fn run(&self) -> Vec<&'a Source> {
+        let mut sources: Vec<&'a Source> =
+            self.sources.iter().filter(|s| self.matches(s)).collect();
+        if let Some((key, order)) = self.sort {
+            sources.sort_by(|a, b| {
+                let ordering = compare(a, b, key);
+                match order {
+                    Order::Ascending => ordering,
+                    Order::Descending => ordering.reverse(),
+                }
+            });
+        }
+        sources
+    }
+}
+
+fn compare(a: &Source, b: &Source, key: SortKey) -> Ordering {
+    match key {
+        SortKey::LastUpdated => a.last_updated().cmp(b.last_updated()),
+        SortKey::InteractionCount => a.interaction_count().cmp(&b.interaction_count()),
+        SortKey::Designation => a.journalist_designation().cmp(b.journalist_designation()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use data::Sources;
+    use json;
+
+    fn source(n: u32, starred: bool, documents: u32) -> String {
+        format!(
+            r#"{{
+                "uuid": "00000000-0000-0000-0000-00000000000{n}",
+                "flagged": false,
+                "is_starred": {starred},
+                "last_updated": "2018-10-0{n}T00:00:00Z",
+                "interaction_count": {count},
+                "journalist_designation": "source {n}",
+                "number_of_documents": {documents},
+                "number_of_messages": 0,
+                "public_key": null
+            }}"#,
+            n = n,
+            starred = starred,
+            count = 10 - n,
+            documents = documents,
+        )
+    }
+
+    fn submission(i: usize, source: u32, is_read: bool) -> Submission {
+        json::from_str(&format!(
+            r#"{{
+                "uuid": "00000000-0000-0000-0001-00000000000{i}",
+                "source_uuid": "00000000-0000-0000-0000-00000000000{source}",
+                "filename": "{i}-source_{source}-msg.gpg",
+                "is_read": {is_read},
+                "size": 604
+            }}"#,
+            i = i,
+            source = source,
+            is_read = is_read,
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn filter_and_sort() {
+        let sources: Sources = json::from_str(&format!(
+            r#"{{ "sources": [{}, {}, {}, {}] }}"#,
+            source(1, true, 0),
+            source(2, true, 2),
+            source(3, false, 3),
+            source(4, true, 1),
+        ))
+        .unwrap();
+        let names = |query: SourceQuery| -> Vec<String> {
+            query
+                .run()
+                .iter()
+                .map(|s| s.journalist_designation().to_string())
+                .collect()
+        };
+
+        assert_eq!(names(sources.query()).len(), 4);
+        assert_eq!(
+            names(
+                sources
+                    .query()
+                    .starred()
+                    .min_documents(1)
+                    .sort_by(SortKey::InteractionCount, Order::Ascending)
+            ),
+            vec!["source 4", "source 2"]
+        );
+        assert_eq!(
+            names(
+                sources
+                    .query()
+                    .updated_since("2018-10-03T00:00:00Z".parse().unwrap())
+                    .sort_by(SortKey::LastUpdated, Order::Descending)
+            ),
+            vec!["source 4", "source 3"]
+        );
+        assert_eq!(
+            names(sources.query().designation_contains("SOURCE 1")),
+            vec!["source 1"]
+        );
+        let none: Vec<&Submission> = Vec::new();
+        assert!(names(sources.query().has_unread(none)).is_empty());
+
+        let submissions: Vec<Submission> = vec![(2, false), (3, true), (4, false), (4, true)]
+            .into_iter()
+            .enumerate()
+            .map(|(i, (n, is_read))| submission(i, n, is_read))
+            .collect();
+        assert_eq!(
+            names(
+                sources
+                    .query()
+                    .has_unread(&submissions)
+                    .sort_by(SortKey::Designation, Order::Ascending)
+            ),
+            vec!["source 2", "source 4"]
+        );
+        assert_eq!(
+            names(sources.query().starred().has_unread(&submissions[..2])),
+            vec!["source 2"]
+        );
+    }
+}
@@ -0,0 +1,62 @@
+//! A wrapper for sensitive strings.
+
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::fmt::{self, Debug};
+use zeroize::Zeroize;
+
+/// A string that should not be leaked, such as a passphrase, one time code, or API token.
+///
+/// The contents are wiped from memory when the `Secret` is dropped, and are never printed by
+/// `Debug`. Use `expose_secret` to deliberately access the value.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    /// Wrap a sensitive string.
+    pub fn new(secret: String) -> Self {
+        Secret(secret)
+    }
+
+    /// Access the secret value. Take care not to log or otherwise copy it.
+    pub fn expose_secret(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(secret: String) -> Self {
+        Self::new(secret)
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+/// Secrets serialize as the plain string since they are sent to the API and saved in token stores.
+impl Serialize for Secret {
+    fn serialize<S>(&self, ser: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        ser.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D>(de: D) -> ::std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(de).map(Secret)
+    }
+}
@@ -0,0 +1,432 @@
+//! A SQLite backed `sync::Store` for offline use.
+//!
+//! This module is only available with the `sqlite` feature enabled.
+//!
+//! Each record is stored as JSON alongside a few indexed columns, so fields this crate does not
+//! know about survive a round trip. The store also tracks whether each submission and reply has
+//! been downloaded (and decrypted) and where the local copy lives. Download state is kept when a
+//! sync updates the record's metadata.
+//!
+//! ```no_run
+//! # extern crate securedrop_api;
+//! use securedrop_api::sqlite::{DownloadState, SqliteStore};
+//! use securedrop_api::sync::{Store, Syncer};
+//!
+//! # fn main() {
+//! let store = SqliteStore::open("securedrop.sqlite").unwrap();
+//! let mut syncer = Syncer::new(store);
+//! // The UI can be rendered from `syncer.store()` before the first sync completes
+//! let sources = syncer.store().sources().unwrap();
+//! # }
+//! ```
+
+use json;
+use rusqlite::{self, Connection, OptionalExtension, Transaction};
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use std::path::{Path, PathBuf};
+
+use super::Result;
+use data::{Journalist, ReplyId, SentReply, Source, SourceId, Submission, SubmissionId};
+use error::{Error, ErrorKind};
+use sync::{ChangeSet, Store};
+
+/// Schema migrations. The schema version (`PRAGMA user_version`) is the number of migrations that
+/// have been applied. Never edit a migration that has been released; add a new one instead.
+const MIGRATIONS: &[&str] = &[r#"
+CREATE TABLE sources (
+    uuid TEXT PRIMARY KEY NOT NULL,
+    journalist_designation TEXT NOT NULL,
+    last_updated TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+
+CREATE TABLE submissions (
+    uuid TEXT PRIMARY KEY NOT NULL,
+    source_uuid TEXT NOT NULL REFERENCES sources (uuid) ON DELETE CASCADE,
+    filename TEXT NOT NULL,
+    data TEXT NOT NULL,
+    download_state TEXT NOT NULL DEFAULT 'not_downloaded',
+    local_path TEXT
+);
+CREATE INDEX submissions_source_uuid ON submissions (source_uuid);
+
+CREATE TABLE replies (
+    uuid TEXT PRIMARY KEY NOT NULL,
+    source_uuid TEXT NOT NULL REFERENCES sources (uuid) ON DELETE CASCADE,
+    filename TEXT NOT NULL,
+    data TEXT NOT NULL,
+    download_state TEXT NOT NULL DEFAULT 'not_downloaded',
+    local_path TEXT
+);
+CREATE INDEX replies_source_uuid ON replies (source_uuid);
+
+CREATE TABLE journalists (
+    uuid TEXT PRIMARY KEY NOT NULL,
+    username TEXT NOT NULL,
+    data TEXT NOT NULL
+);
+"#];
+
+/// Whether a submission or reply is available locally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DownloadState {
+    /// Only the metadata is stored.
+    NotDownloaded,
+    /// The encrypted file has been downloaded.
+    Downloaded,
+    /// The file has been downloaded and decrypted.
+    Decrypted,
+}
+
+impl DownloadState {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            DownloadState::NotDownloaded => "not_downloaded",
+            DownloadState::Downloaded => "downloaded",
+            DownloadState::Decrypted => "decrypted",
+        }
+    }
+
+    fn parse(s: &str) -> Result<Self> {
+        match s {
+            "not_downloaded" => Ok(DownloadState::NotDownloaded),
+            "downloaded" => Ok(DownloadState::Downloaded),
+            "decrypted" => Ok(DownloadState::Decrypted),
+            _ => Err(ErrorKind::IO(format!("Invalid download state: {}", s)).into()),
+        }
+    }
+}
+
+/// The download state of a submission or reply and where the local copy is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Download {
+    state: DownloadState,
+    local_path: Option<PathBuf>,
+}
+
+impl Download {
+    /// Whether the file is available locally.
+    pub fn state(&self) -> DownloadState {
+        self.state
+    }
+
+    /// The path of the local copy, if there is one.
+    pub fn local_path(&self) -> Option<&Path> {
+        self.local_path.as_ref().map(PathBuf::as_path)
+    }
+}
+
+/// A `Store` backed by a SQLite database.
+#[derive(Debug)]
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    /// Open (or create) the database at `path` and migrate it to the current schema.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_connection(Connection::open(path).map_err(sql_error)?)
+    }
+
+    /// Create a temporary in-memory database.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory().map_err(sql_error)?)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<Self> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")
+            .map_err(sql_error)?;
+        migrate(&mut conn)?;
+        Ok(Self { conn })
+    }
+
+    /// The version of the database schema.
+    pub fn schema_version(&self) -> Result<usize> {
+        schema_version(&self.conn)
+    }
+
+    /// The download state of a submission, or `None` if the submission is not stored.
+    pub fn submission_download(&self, id: &SubmissionId) -> Result<Option<Download>> {
+        download(&self.conn, "submissions", &id.to_string())
+    }
+
+    /// Record the download state of a submission. Returns `Err` if the submission is not stored.
+    pub fn set_submission_download(
+        &self,
+        id: &SubmissionId,
+        state: DownloadState,
+        local_path: Option<&Path>,
+    ) -> Result<()> {
+        set_download(
+            &self.conn,
+            "submissions",
+            &id.to_string(),
+            state,
+            local_path,
+        )
+    }
+
+    /// The download state of a reply, or `None` if the reply is not stored.
+    pub fn reply_download(&self, id: &ReplyId) -> Result<Option<Download>> {
+        download(&self.conn, "replies", &id.to_string())
+    }
+
+    /// Record the download state of a reply. Returns `Err` if the reply is not stored.
+    pub fn set_reply_download(
+        &self,
+        id: &ReplyId,
+        state: DownloadState,
+        local_path: Option<&Path>,
+    ) -> Result<()> {
+        set_download(&self.conn, "replies", &id.to_string(), state, local_path)
+    }
+
+    fn load<T: DeserializeOwned>(&self, sql: &str, param: Option<&str>) -> Result<Vec<T>> {
+        let mut stmt = self.conn.prepare(sql).map_err(sql_error)?;
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(param), |row| {
+                row.get::<_, String>(0)
+            })
+            .map_err(sql_error)?;
+        rows.map(|data| {
+            let data = data.map_err(sql_error)?;
+            json::from_str(&data).map_err(|e| ErrorKind::IO(e.to_string()).into())
+        })
+        .collect()
+    }
+}
+
+impl Store for SqliteStore {
+    fn sources(&self) -> Result<Vec<Source>> {
+        self.load("SELECT data FROM sources ORDER BY uuid", None)
+    }
+
+    fn submissions(&self, source: &SourceId) -> Result<Vec<Submission>> {
+        self.load(
+            "SELECT data FROM submissions WHERE source_uuid = ? ORDER BY uuid",
+            Some(&source.to_string()),
+        )
+    }
+
+    fn replies(&self, source: &SourceId) -> Result<Vec<SentReply>> {
+        self.load(
+            "SELECT data FROM replies WHERE source_uuid = ? ORDER BY uuid",
+            Some(&source.to_string()),
+        )
+    }
+
+    fn journalists(&self) -> Result<Vec<Journalist>> {
+        self.load("SELECT data FROM journalists ORDER BY uuid", None)
+    }
+
+    fn apply(&mut self, changes: &ChangeSet) -> Result<()> {
+        let tx = self.conn.transaction().map_err(sql_error)?;
+
+        let sources = changes.sources();
+        for source in sources.added().iter().chain(sources.changed()) {
+            tx.execute(
+                "INSERT INTO sources (uuid, journalist_designation, last_updated, data)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT (uuid) DO UPDATE SET
+                    journalist_designation = ?2, last_updated = ?3, data = ?4",
+                [
+                    source.uuid().to_string(),
+                    source.journalist_designation().to_string(),
+                    source.last_updated().to_rfc3339(),
+                    to_json(source)?,
+                ],
+            )
+            .map_err(sql_error)?;
+        }
+
+        let submissions = changes.submissions();
+        for sub in submissions.added().iter().chain(submissions.changed()) {
+            upsert_file(
+                &tx,
+                "submissions",
+                &sub.uuid().to_string(),
+                &sub.source_uuid().to_string(),
+                sub.filename(),
+                &to_json(sub)?,
+            )?;
+        }
+
+        let replies = changes.replies();
+        for reply in replies.added().iter().chain(replies.changed()) {
+            let source = reply
+                .source_uuid()
+                .ok_or_else(|| ErrorKind::ProgrammingError("Synced reply has no source".into()))?;
+            upsert_file(
+                &tx,
+                "replies",
+                &reply.uuid().to_string(),
+                &source.to_string(),
+                reply.filename(),
+                &to_json(reply)?,
+            )?;
+        }
+
+        let journalists = changes.journalists();
+        for journalist in journalists.added().iter().chain(journalists.changed()) {
+            tx.execute(
+                "INSERT INTO journalists (uuid, username, data) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (uuid) DO UPDATE SET username = ?2, data = ?3",
+                [
+                    journalist.uuid().to_string(),
+                    journalist.username().to_string(),
+                    to_json(journalist)?,
+                ],
+            )
+            .map_err(sql_error)?;
+        }
+
+        let deletes = sources
+            .deleted()
+            .iter()
+            .map(|id| ("sources", id.to_string()))
+            .chain(
+                submissions
+                    .deleted()
+                    .iter()
+                    .map(|id| ("submissions", id.to_string())),
+            )
+            .chain(
+                replies
+                    .deleted()
+                    .iter()
+                    .map(|id| ("replies", id.to_string())),
+            )
+            .chain(
+                journalists
+                    .deleted()
+                    .iter()
+                    .map(|id| ("journalists", id.to_string())),
+            );
+        for (table, id) in deletes {
+            tx.execute(&format!("DELETE FROM {} WHERE uuid = ?", table), [id])
+                .map_err(sql_error)?;
+        }
+
+        tx.commit().map_err(sql_error)
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version = schema_version(conn)?;
+    if version > MIGRATIONS.len() {
+        return Err(ErrorKind::IO(format!(
+            "Database schema version {} is newer than this crate supports",
+            version
+        ))
+        .into());
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction().map_err(sql_error)?;
+        tx.execute_batch(migration).map_err(sql_error)?;
+        tx.pragma_update(None, "user_version", i + 1)
+            .map_err(sql_error)?;
+        tx.commit().map_err(sql_error)?;
+    }
+    Ok(())
+}
+
+fn schema_version(conn: &Connection) -> Result<usize> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0))
+        .map(|v| v as usize)
+        .map_err(sql_error)
+}
+
+fn upsert_file(
+    tx: &Transaction,
+    table: &str,
+    uuid: &str,
+    source_uuid: &str,
+    filename: &str,
+    data: &str,
+) -> Result<()> {
+    // Download state is deliberately left alone when the record already exists
+    tx.execute(
+        &format!(
+            "INSERT INTO {} (uuid, source_uuid, filename, data) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT (uuid) DO UPDATE SET source_uuid = ?2, filename = ?3, data = ?4",
+            table
+        ),
+        [uuid, source_uuid, filename, data],
+    )
+    .map_err(sql_error)?;
+    Ok(())
+}
+
+fn download(conn: &Connection, table: &str, uuid: &str) -> Result<Option<Download>> {
+    let row = conn
+        .query_row(
+            &format!(
+                "SELECT download_state, local_path FROM {} WHERE uuid = ?",
+                table
+            ),
+            [uuid],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)),
+        )
+        .optional()
+        .map_err(sql_error)?;
+    match row {
+        Some((state, local_path)) => Ok(Some(Download {
+            state: DownloadState::parse(&state)?,
+            local_path: local_path.map(PathBuf::from),
+        })),
+        None => Ok(None),
+    }
+}
+
+fn set_download(
+    conn: &Connection,
+    table: &str,
+    uuid: &str,
+    state: DownloadState,
+    local_path: Option<&Path>,
+) -> Result<()> {
+    let local_path = match local_path {
+        Some(path) => Some(
+            path.to_str()
+                .ok_or_else(|| ErrorKind::IO("Local path is not valid UTF-8".into()))?
+                .to_string(),
+        ),
+        None => None,
+    };
+    let updated = conn
+        .execute(
+            &format!(
+                "UPDATE {} SET download_state = ?, local_path = ? WHERE uuid = ?",
+                table
+            ),
+            rusqlite::params![state.as_str(), local_path, uuid],
+        )
+        .map_err(sql_error)?;
+    if updated == 0 {
+        return Err(ErrorKind::ClientError(format!("No such item: {}", uuid)).into());
+    }
+    Ok(())
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String> {
+    json::to_string(value).map_err(|e| ErrorKind::ProgrammingError(e.to_string()).into())
+}
+
+fn sql_error(e: rusqlite::Error) -> Error {
+    ErrorKind::IO(format!("SQLite error: {}", e)).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn migrations_are_idempotent() {
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        assert_eq!(store.schema_version().unwrap(), MIGRATIONS.len());
+        migrate(&mut store.conn).unwrap();
+        assert_eq!(store.schema_version().unwrap(), MIGRATIONS.len());
+    }
+}
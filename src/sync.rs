@@ -0,0 +1,368 @@
+//! Keep a local copy of the server's data up to date.
+//!
+//! A `Syncer` fetches the current state from the server, compares it against what is held in a
+//! `Store`, applies the differences to the store, and returns them as a `ChangeSet` so that
+//! applications can update their views without refetching everything.
+//!
+//! Submissions and replies are only refetched for sources that are new or whose metadata (e.g.,
+//! `last_updated` or the number of messages) has changed, so a sync where nothing happened costs
+//! a single request for the source list plus one for the journalist list. The catch is that
+//! changes which leave the source untouched are missed: most notably, marking submissions seen
+//! (`Submission::is_read`) does not change the source, so the store keeps the old value. Use
+//! `Syncer::refresh`, which refetches every source's submissions and replies, when those need to
+//! be current (e.g., before `query::SourceQuery::has_unread`).
+//!
+//! ```no_run
+//! # extern crate securedrop_api;
+//! use securedrop_api::auth::UserPassTotp;
+//! use securedrop_api::sync::{MemoryStore, Syncer};
+//! use securedrop_api::Client;
+//!
+//! # fn main() {
+//! # let creds = UserPassTotp::new("journalist".into(), "pass".into(), "123456".into());
+//! let client = Client::new("http://localhost:8081".parse().unwrap(), creds, None).unwrap();
+//! let mut syncer = Syncer::new(MemoryStore::new());
+//! let changes = syncer.sync(&client).unwrap();
+//! for source in changes.sources().added() {
+//!     println!("New source: {}", source.journalist_designation());
+//! }
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::Result;
+use client::Client;
+use data::{
+    Journalist, JournalistId, ReplyId, SentReply, Source, SourceId, Submission, SubmissionId,
+};
+
+/// Added, changed, and deleted items of one type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Changes<T, K> {
+    pub(crate) added: Vec<T>,
+    pub(crate) changed: Vec<T>,
+    pub(crate) deleted: Vec<K>,
+}
+
+impl<T, K> Changes<T, K> {
+    /// Items that were not in the store.
+    pub fn added(&self) -> &[T] {
+        &self.added
+    }
+
+    /// Items that were in the store but differ from the server's copy.
+    pub fn changed(&self) -> &[T] {
+        &self.changed
+    }
+
+    /// IDs of items that were in the store but are no longer on the server.
+    pub fn deleted(&self) -> &[K] {
+        &self.deleted
+    }
+
+    /// Whether there are no changes.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.deleted.is_empty()
+    }
+}
+
+impl<T, K> Default for Changes<T, K> {
+    fn default() -> Self {
+        Self {
+            added: Vec::new(),
+            changed: Vec::new(),
+            deleted: Vec::new(),
+        }
+    }
+}
+
+/// Everything that changed during a sync.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeSet {
+    pub(crate) sources: Changes<Source, SourceId>,
+    pub(crate) submissions: Changes<Submission, SubmissionId>,
+    pub(crate) replies: Changes<SentReply, ReplyId>,
+    pub(crate) journalists: Changes<Journalist, JournalistId>,
+}
+
+impl ChangeSet {
+    /// Changes to sources.
+    pub fn sources(&self) -> &Changes<Source, SourceId> {
+        &self.sources
+    }
+
+    /// Changes to submissions.
+    pub fn submissions(&self) -> &Changes<Submission, SubmissionId> {
+        &self.submissions
+    }
+
+    /// Changes to replies. Replies always have `SentReply::source_uuid` set.
+    pub fn replies(&self) -> &Changes<SentReply, ReplyId> {
+        &self.replies
+    }
+
+    /// Changes to journalists.
+    pub fn journalists(&self) -> &Changes<Journalist, JournalistId> {
+        &self.journalists
+    }
+
+    /// Whether nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+            && self.submissions.is_empty()
+            && self.replies.is_empty()
+            && self.journalists.is_empty()
+    }
+}
+
+/// Local storage for synced data.
+pub trait Store {
+    /// All stored sources.
+    fn sources(&self) -> Result<Vec<Source>>;
+
+    /// All stored submissions for a source.
+    fn submissions(&self, source: &SourceId) -> Result<Vec<Submission>>;
+
+    /// All stored replies for a source.
+    fn replies(&self, source: &SourceId) -> Result<Vec<SentReply>>;
+
+    /// All stored journalists.
+    fn journalists(&self) -> Result<Vec<Journalist>>;
+
+    /// Apply the changes from a sync. Implementations should apply them atomically if they can.
+    fn apply(&mut self, changes: &ChangeSet) -> Result<()>;
+}
+
+/// A `Store` that keeps everything in memory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryStore {
+    sources: BTreeMap<SourceId, Source>,
+    submissions: BTreeMap<SubmissionId, Submission>,
+    replies: BTreeMap<ReplyId, SentReply>,
+    journalists: BTreeMap<JournalistId, Journalist>,
+}
+
+impl MemoryStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Store for MemoryStore {
+    fn sources(&self) -> Result<Vec<Source>> {
+        Ok(self.sources.values().cloned().collect())
+    }
+
+    fn submissions(&self, source: &SourceId) -> Result<Vec<Submission>> {
+        Ok(self
+            .submissions
+            .values()
+            .filter(|s| s.source_uuid() == source)
+            .cloned()
+            .collect())
+    }
+
+    fn replies(&self, source: &SourceId) -> Result<Vec<SentReply>> {
+        Ok(self
+            .replies
+            .values()
+            .filter(|r| r.source_uuid() == Some(source))
+            .cloned()
+            .collect())
+    }
+
+    fn journalists(&self) -> Result<Vec<Journalist>> {
+        Ok(self.journalists.values().cloned().collect())
+    }
+
+    fn apply(&mut self, changes: &ChangeSet) -> Result<()> {
+        apply_changes(&mut self.sources, &changes.sources, |s| *s.uuid());
+        apply_changes(&mut self.submissions, &changes.submissions, |s| *s.uuid());
+        apply_changes(&mut self.replies, &changes.replies, |r| *r.uuid());
+        apply_changes(&mut self.journalists, &changes.journalists, |j| *j.uuid());
+        Ok(())
+    }
+}
+
+fn apply_changes<T, K, F>(map: &mut BTreeMap<K, T>, changes: &Changes<T, K>, key: F)
+where
+    T: Clone,
+    K: Ord + Clone,
+    F: Fn(&T) -> K,
+{
+    for item in changes.added.iter().chain(&changes.changed) {
+        map.insert(key(item), item.clone());
+    }
+    for id in &changes.deleted {
+        map.remove(id);
+    }
+}
+
+/// Syncs a `Store` with the server.
+#[derive(Debug)]
+pub struct Syncer<S> {
+    store: S,
+}
+
+impl<S: Store> Syncer<S> {
+    /// Sync into the given store.
+    pub fn new(store: S) -> Self {
+        Self { store }
+    }
+
+    /// The store being synced.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// The store being synced, for making local changes (e.g., with an `outbox::Outbox`).
+    pub fn store_mut(&mut self) -> &mut S {
+        &mut self.store
+    }
+
+    /// Consume the syncer and return the store.
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Fetch the current state from the server, apply any differences to the store, and return
+    /// them. If the request fails part way, the store is left unchanged.
+    pub fn sync(&mut self, client: &Client) -> Result<ChangeSet> {
+        self.sync_sources(client, false)
+    }
+
+    /// Sync like `Syncer::sync`, but refetch the submissions and replies of every source, not
+    /// only those whose metadata changed. This costs two requests per source, and picks up
+    /// changes that do not touch the source, such as submissions being marked seen.
+    pub fn refresh(&mut self, client: &Client) -> Result<ChangeSet> {
+        self.sync_sources(client, true)
+    }
+
+    fn sync_sources(&mut self, client: &Client, refetch_all: bool) -> Result<ChangeSet> {
+        let changes = self.changes(client, refetch_all)?;
+        if !changes.is_empty() {
+            self.store.apply(&changes)?;
+        }
+        Ok(changes)
+    }
+
+    fn changes(&self, client: &Client, refetch_all: bool) -> Result<ChangeSet> {
+        let mut changes = ChangeSet::default();
+
+        let stored: BTreeMap<SourceId, Source> = self
+            .store
+            .sources()?
+            .into_iter()
+            .map(|s| (*s.uuid(), s))
+            .collect();
+        let fetched = client.sources()?;
+        let fetched_ids: BTreeSet<SourceId> = fetched.sources().iter().map(|s| *s.uuid()).collect();
+
+        for source in fetched.sources() {
+            let id = source.uuid();
+            match stored.get(id) {
+                Some(old) if old == source => {
+                    if !refetch_all {
+                        continue;
+                    }
+                }
+                Some(_) => changes.sources.changed.push(source.clone()),
+                None => changes.sources.added.push(source.clone()),
+            }
+
+            let submissions = client.source_submissions(id)?.submissions().to_vec();
+            let replies = client
+                .source_replies(id)?
+                .replies()
+                .iter()
+                .cloned()
+                .map(|mut r| {
+                    r.set_source_uuid(*id);
+                    r
+                })
+                .collect();
+            diff(
+                &mut changes.submissions,
+                self.store.submissions(id)?,
+                submissions,
+                |s| *s.uuid(),
+            );
+            diff(
+                &mut changes.replies,
+                self.store.replies(id)?,
+                replies,
+                |r| *r.uuid(),
+            );
+        }
+
+        for id in stored.keys().filter(|id| !fetched_ids.contains(id)) {
+            changes.sources.deleted.push(*id);
+            diff(
+                &mut changes.submissions,
+                self.store.submissions(id)?,
+                Vec::new(),
+                |s| *s.uuid(),
+            );
+            diff(
+                &mut changes.replies,
+                self.store.replies(id)?,
+                Vec::new(),
+                |r| *r.uuid(),
+            );
+        }
+
+        // Older servers do not list journalists (and respond with `404 Not Found` or `405 Method
+        // Not Allowed`), so leave the stored ones alone
+        match client.users() {
+            Ok(users) => diff(
+                &mut changes.journalists,
+                self.store.journalists()?,
+                users.users().to_vec(),
+                |j| *j.uuid(),
+            ),
+            Err(ref e) if e.status() == Some(404) || e.status() == Some(405) => (),
+            Err(e) => return Err(e),
+        }
+
+        Ok(changes)
+    }
+}
+
+/// Compare the stored and fetched items and add the differences to `changes`.
+fn diff<T, K, F>(changes: &mut Changes<T, K>, stored: Vec<T>, fetched: Vec<T>, key: F)
+where
+    T: PartialEq,
+    K: Ord,
+    F: Fn(&T) -> K,
+{
+    let mut stored: BTreeMap<K, T> = stored.into_iter().map(|t| (key(&t), t)).collect();
+    for item in fetched {
+        match stored.remove(&key(&item)) {
+            Some(ref old) if *old == item => (),
+            Some(_) => changes.changed.push(item),
+            None => changes.added.push(item),
+        }
+    }
+    changes.deleted.extend(stored.into_iter().map(|(k, _)| k));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diff_items() {
+        let mut changes = Changes::default();
+        diff(
+            &mut changes,
+            vec![(1, "a"), (2, "b"), (3, "c")],
+            vec![(1, "a"), (2, "B"), (4, "d")],
+            |&(k, _)| k,
+        );
+        assert_eq!(changes.added(), &[(4, "d")]);
+        assert_eq!(changes.changed(), &[(2, "B")]);
+        assert_eq!(changes.deleted(), &[3]);
+    }
+}
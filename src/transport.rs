@@ -0,0 +1,322 @@
+//! HTTP transports used by `Client` to talk to the SecureDrop API.
+//!
+//! `Client` sends every request through a `Transport`. By default this is `ReqwestTransport`, but
+//! any implementation can be passed to `Client::with_transport` (e.g., `proxy::ProxyTransport` to
+//! route requests through a proxy process, or `cassette::Replayer` to serve canned responses in
+//! tests).
+
+use reqwest::header::Headers;
+use reqwest::{self, Client as HttpClient, Url};
+use std::fmt::{self, Debug, Display};
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+use std::time::Duration;
+use zeroize::Zeroize;
+
+use super::Result;
+use error::{Error, ErrorKind};
+
+const REDACTED: &str = "[REDACTED]";
+
+/// HTTP methods used by the SecureDrop API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Method {
+    /// `GET`
+    Get,
+    /// `POST`
+    Post,
+    /// `DELETE`
+    Delete,
+}
+
+impl Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Delete => "DELETE",
+        })
+    }
+}
+
+/// An HTTP request to be sent by a `Transport`. The `Authorization` header and the body are
+/// redacted from `Debug` output, since they carry tokens and passphrases.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Request {
+    method: Method,
+    url: Url,
+    headers: Vec<(String, String)>,
+    body: Option<Vec<u8>>,
+    timeout: Option<Duration>,
+}
+
+impl Request {
+    /// Create a new request with no headers and no body.
+    pub fn new(method: Method, url: Url) -> Self {
+        Self {
+            method,
+            url,
+            headers: Vec::new(),
+            body: None,
+            timeout: None,
+        }
+    }
+
+    /// Set a header, replacing any existing header with the same (case insensitive) name.
+    pub fn set_header<K, V>(&mut self, name: K, value: V)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let name = name.into();
+        for &mut (ref n, ref mut v) in &mut self.headers {
+            if n.eq_ignore_ascii_case(&name) {
+                v.zeroize();
+            }
+        }
+        self.headers
+            .retain(|&(ref n, _)| !n.eq_ignore_ascii_case(&name));
+        self.headers.push((name, value.into()));
+    }
+
+    /// Set the request body.
+    pub fn set_body(&mut self, body: Vec<u8>) {
+        self.body = Some(body);
+    }
+
+    /// Set how long the transport should wait for a response.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// The HTTP method.
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// The full URL of the request.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// All request headers as `(name, value)` pairs.
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Look up a header by its (case insensitive) name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// The request body, if any.
+    pub fn body(&self) -> Option<&[u8]> {
+        self.body.as_ref().map(Vec::as_slice)
+    }
+
+    /// How long the transport should wait for a response. `None` means the transport's default.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
+
+impl Debug for Request {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let headers: Vec<(&str, &str)> = self
+            .headers
+            .iter()
+            .map(|&(ref name, ref value)| {
+                if name.eq_ignore_ascii_case("Authorization") {
+                    (name.as_str(), REDACTED)
+                } else {
+                    (name.as_str(), value.as_str())
+                }
+            })
+            .collect();
+        f.debug_struct("Request")
+            .field("method", &self.method)
+            .field("url", &self.url)
+            .field("headers", &headers)
+            .field("body", &self.body.as_ref().map(|_| REDACTED))
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl Drop for Request {
+    fn drop(&mut self) {
+        // Header values and bodies carry tokens and passphrases
+        for &mut (_, ref mut value) in &mut self.headers {
+            value.zeroize();
+        }
+        self.body.zeroize();
+    }
+}
+
+/// An HTTP response returned by a `Transport`. The body is streamed so that large downloads do
+/// not need to be held in memory.
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Box<dyn Read + Send>,
+}
+
+impl Response {
+    /// Create a new response with a streaming body.
+    pub fn new<R>(status: u16, headers: Vec<(String, String)>, body: R) -> Self
+    where
+        R: Read + Send + 'static,
+    {
+        Self {
+            status,
+            headers,
+            body: Box::new(body),
+        }
+    }
+
+    /// Create a new response with a body that is already in memory.
+    pub fn from_bytes(status: u16, headers: Vec<(String, String)>, body: Vec<u8>) -> Self {
+        Self::new(status, headers, Cursor::new(body))
+    }
+
+    /// The HTTP status code.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// All response headers as `(name, value)` pairs.
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Look up a header by its (case insensitive) name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// The response body as a stream.
+    pub fn body(&mut self) -> &mut dyn Read {
+        &mut self.body
+    }
+
+    /// Consume the response and return the body stream.
+    pub fn into_body(self) -> Box<dyn Read + Send> {
+        self.body
+    }
+
+    /// Consume the response and read the entire body into memory.
+    pub fn read_body(self) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        let mut stream = self.body;
+        stream
+            .read_to_end(&mut body)
+            .map_err(|e| Error::new(ErrorKind::IO(format!("{:?}", e))))?;
+        Ok(body)
+    }
+}
+
+impl Debug for Response {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Response")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|&&(ref n, _)| n.eq_ignore_ascii_case(name))
+        .map(|&(_, ref v)| v.as_str())
+}
+
+/// Something that can send HTTP requests on behalf of a `Client`.
+///
+/// Implementations should only return `Err` when no response was received (e.g., the network was
+/// unreachable). HTTP error statuses are returned as a `Response` and are interpreted by the
+/// `Client`. Transports must be `Send + Sync` so that a `Client` can be shared between threads.
+pub trait Transport: Send + Sync {
+    /// Send a request and wait for the response headers. The body may continue to stream after
+    /// this returns.
+    fn send(&self, request: &Request) -> Result<Response>;
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn send(&self, request: &Request) -> Result<Response> {
+        (**self).send(request)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn send(&self, request: &Request) -> Result<Response> {
+        (**self).send(request)
+    }
+}
+
+/// The default transport that sends requests directly using `reqwest`.
+///
+/// `reqwest` does not support per-request timeouts, so `Request::timeout` is ignored. To limit how
+/// long requests may take, configure the `reqwest::Client` and use `ReqwestTransport::from_client`.
+pub struct ReqwestTransport {
+    http: HttpClient,
+}
+
+impl ReqwestTransport {
+    /// Create a transport using a default `reqwest::Client`.
+    pub fn new() -> Self {
+        Self::from_client(HttpClient::new())
+    }
+
+    /// Create a transport using a preconfigured `reqwest::Client` (e.g., one that uses a SOCKS
+    /// proxy).
+    pub fn from_client(http: HttpClient) -> Self {
+        Self { http }
+    }
+}
+
+impl Default for ReqwestTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(&self, request: &Request) -> Result<Response> {
+        let method = match request.method() {
+            Method::Get => reqwest::Method::Get,
+            Method::Post => reqwest::Method::Post,
+            Method::Delete => reqwest::Method::Delete,
+        };
+        let mut headers = Headers::new();
+        for &(ref name, ref value) in request.headers() {
+            headers.set_raw(name.clone(), value.clone());
+        }
+
+        let mut builder = self.http.request(method, request.url().clone());
+        builder.headers(headers);
+        if let Some(body) = request.body() {
+            builder.body(body.to_vec());
+        }
+
+        let resp = builder.send().map_err(map_reqwest_error)?;
+        let status = resp.status().as_u16();
+        let headers = resp
+            .headers()
+            .iter()
+            .map(|h| (h.name().to_string(), h.value_string()))
+            .collect();
+        Ok(Response::new(status, headers, resp))
+    }
+}
+
+fn map_reqwest_error(err: reqwest::Error) -> Error {
+    if !err.is_http() {
+        ErrorKind::NetworkError.into()
+    } else if err.is_server_error() {
+        ErrorKind::ServerError.into()
+    } else {
+        ErrorKind::UnknownError.into()
+    }
+}
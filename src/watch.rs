@@ -0,0 +1,298 @@
+//! Poll the server for changes and report them as events.
+//!
+//! A `Watcher` syncs a `Store` with the server at a fixed interval (see `sync`) and turns the
+//! differences into `Event`s, so applications can react to new submissions without comparing
+//! lists themselves. Events can be read from a blocking iterator with `Watcher::events`, or from
+//! a channel fed by a background thread with `Watcher::spawn`, which is stopped through the
+//! returned `WatchHandle`.
+//!
+//! The first poll reports everything already on the server as new. Call `Watcher::baseline`
+//! first to only hear about changes made after the watcher started.
+//!
+//! ```no_run
+//! # extern crate securedrop_api;
+//! use securedrop_api::auth::UserPassTotp;
+//! use securedrop_api::watch::{Event, Watcher};
+//! use securedrop_api::Client;
+//! use std::time::Duration;
+//!
+//! # fn main() {
+//! # let creds = UserPassTotp::new("journalist".into(), "pass".into(), "123456".into());
+//! let client = Client::new("http://localhost:8081".parse().unwrap(), creds, None).unwrap();
+//! let mut watcher = Watcher::new().interval(Duration::from_secs(30));
+//! watcher.baseline(&client).unwrap();
+//! for event in watcher.events(&client) {
+//!     match event {
+//!         Ok(Event::NewSubmission(submission)) => println!("New: {}", submission.filename()),
+//!         Ok(_) => (),
+//!         Err(e) => println!("Poll failed: {}", e),
+//!     }
+//! }
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use super::Result;
+use client::Client;
+use data::{SentReply, Source, SourceId, Submission};
+use sync::{ChangeSet, MemoryStore, Store, Syncer};
+
+/// The time between polls unless `Watcher::interval` is set.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A change seen on the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A source appeared.
+    NewSource(Source),
+    /// A source sent a message or document.
+    NewSubmission(Submission),
+    /// A source and all of its submissions were deleted.
+    SourceDeleted(SourceId),
+    /// A source was starred or unstarred.
+    StarChanged {
+        /// The source.
+        source: SourceId,
+        /// Whether the source is now starred.
+        starred: bool,
+    },
+    /// A journalist replied to a source.
+    ReplyAdded(SentReply),
+}
+
+/// Polls the server and reports changes as events.
+#[derive(Debug)]
+pub struct Watcher<S = MemoryStore> {
+    syncer: Syncer<S>,
+    interval: Duration,
+}
+
+impl Watcher<MemoryStore> {
+    /// Watch with an empty in-memory store.
+    pub fn new() -> Self {
+        Self::with_store(MemoryStore::new())
+    }
+}
+
+impl Default for Watcher<MemoryStore> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Store> Watcher<S> {
+    /// Watch with the given store. Only changes since the store was last synced are reported.
+    pub fn with_store(store: S) -> Self {
+        Self {
+            syncer: Syncer::new(store),
+            interval: DEFAULT_INTERVAL,
+        }
+    }
+
+    /// Set the time between polls.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// The store holding the last state seen.
+    pub fn store(&self) -> &S {
+        self.syncer.store()
+    }
+
+    /// Consume the watcher and return the store.
+    pub fn into_store(self) -> S {
+        self.syncer.into_store()
+    }
+
+    /// Sync the store without reporting any events.
+    pub fn baseline(&mut self, client: &Client) -> Result<()> {
+        self.syncer.sync(client).map(|_| ())
+    }
+
+    /// Poll the server once and return what changed since the last poll.
+    pub fn poll(&mut self, client: &Client) -> Result<Vec<Event>> {
+        let starred: BTreeMap<SourceId, bool> = self
+            .syncer
+            .store()
+            .sources()?
+            .iter()
+            .map(|s| (*s.uuid(), s.is_starred()))
+            .collect();
+        let changes = self.syncer.sync(client)?;
+        Ok(events(&changes, &starred))
+    }
+
+    /// An endless iterator of events. The first poll happens straight away, and each later poll
+    /// waits for the interval. A failed poll is yielded as an `Err` and does not stop iteration.
+    pub fn events<'a>(&'a mut self, client: &'a Client) -> Events<'a, S> {
+        Events {
+            watcher: self,
+            client,
+            buffer: VecDeque::new(),
+            first: true,
+        }
+    }
+
+    /// Poll on a background thread and send the events to the channel of the returned handle.
+    /// Failed polls are sent as an `Err`. The thread exits once the handle is stopped or dropped,
+    /// without waiting for the rest of the interval.
+    pub fn spawn(mut self, client: Client) -> WatchHandle
+    where
+        S: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = thread::spawn(move || {
+            let mut first = true;
+            loop {
+                if !first {
+                    sleep_unless_stopped(self.interval, &stopped);
+                }
+                first = false;
+                // Checked on every poll, including those that find nothing to send
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let events = match self.poll(&client) {
+                    Ok(events) => events.into_iter().map(Ok).collect(),
+                    Err(e) => vec![Err(e)],
+                };
+                for event in events {
+                    if sender.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        WatchHandle {
+            receiver,
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Sleep for `duration`, waking early if `stop` is set (see `WatchHandle::stop`).
+fn sleep_unless_stopped(duration: Duration, stop: &AtomicBool) {
+    let deadline = Instant::now() + duration;
+    while !stop.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        // Woken by `WatchHandle` when it stops the thread
+        thread::park_timeout(deadline - now);
+    }
+}
+
+/// A watcher polling on a background thread. Created with `Watcher::spawn`.
+///
+/// Dropping the handle stops the thread in the background; `WatchHandle::stop` also waits for it
+/// to exit.
+#[derive(Debug)]
+pub struct WatchHandle {
+    receiver: Receiver<Result<Event>>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl WatchHandle {
+    /// The channel the events are sent to.
+    pub fn events(&self) -> &Receiver<Result<Event>> {
+        &self.receiver
+    }
+
+    /// Stop polling and wait for the thread to exit. A poll that is in progress is finished first.
+    pub fn stop(mut self) {
+        self.signal_stop();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    fn signal_stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(ref thread) = self.thread {
+            thread.thread().unpark();
+        }
+    }
+}
+
+impl Drop for WatchHandle {
+    fn drop(&mut self) {
+        self.signal_stop();
+    }
+}
+
+/// The events from a `Watcher`. Created with `Watcher::events`.
+pub struct Events<'a, S: 'a> {
+    watcher: &'a mut Watcher<S>,
+    client: &'a Client,
+    buffer: VecDeque<Event>,
+    first: bool,
+}
+
+impl<'a, S: Store> Iterator for Events<'a, S> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffer.is_empty() {
+            if !self.first {
+                thread::sleep(self.watcher.interval);
+            }
+            self.first = false;
+            match self.watcher.poll(self.client) {
+                Ok(events) => self.buffer.extend(events),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.buffer.pop_front().map(Ok)
+    }
+}
+
+fn events(changes: &ChangeSet, starred: &BTreeMap<SourceId, bool>) -> Vec<Event> {
+    let mut events: Vec<Event> = changes
+        .sources
+        .added
+        .iter()
+        .cloned()
+        .map(Event::NewSource)
+        .collect();
+    events.extend(
+        changes
+            .sources
+            .changed
+            .iter()
+            .filter(|s| starred.get(s.uuid()) != Some(&s.is_starred()))
+            .map(|s| Event::StarChanged {
+                source: *s.uuid(),
+                starred: s.is_starred(),
+            }),
+    );
+    events.extend(
+        changes
+            .submissions
+            .added
+            .iter()
+            .cloned()
+            .map(Event::NewSubmission),
+    );
+    events.extend(changes.replies.added.iter().cloned().map(Event::ReplyAdded));
+    events.extend(
+        changes
+            .sources
+            .deleted
+            .iter()
+            .cloned()
+            .map(Event::SourceDeleted),
+    );
+    events
+}
@@ -0,0 +1,172 @@
+//! Exercise bulk operations against the mock server.
+
+#![cfg(feature = "mock")]
+
+extern crate securedrop_api;
+
+mod common;
+
+use securedrop_api::bulk::Bulk;
+use securedrop_api::data::{FileKind, SourceId, SubmissionId};
+use securedrop_api::mock::MockServer;
+use securedrop_api::transport::{Method, Request, ReqwestTransport, Response, Transport};
+use securedrop_api::{Client, ErrorKind, Result};
+use std::env;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// Sends the first `DELETE` but reports a network error instead of its response.
+struct LostResponse {
+    inner: ReqwestTransport,
+    lost: AtomicBool,
+}
+
+impl Transport for LostResponse {
+    fn send(&self, request: &Request) -> Result<Response> {
+        let response = self.inner.send(request)?;
+        if request.method() == Method::Delete && !self.lost.swap(true, Ordering::SeqCst) {
+            return Err(ErrorKind::NetworkError.into());
+        }
+        Ok(response)
+    }
+}
+
+#[test]
+fn download_many() {
+    let (mut dataset, source) = common::dataset_with_source();
+    for i in 0..5 {
+        dataset.add_submission(
+            &source,
+            FileKind::Document,
+            format!("doc {}", i).into_bytes(),
+        );
+    }
+    let (server, client) = common::start(dataset);
+    let submissions = client
+        .source_submissions(&source)
+        .unwrap()
+        .submissions()
+        .to_vec();
+    let dest = env::temp_dir().join(format!("securedrop-bulk-test-{}", ::std::process::id()));
+
+    let seen = Mutex::new(Vec::new());
+    let result = Bulk::new(&client)
+        .concurrency(3)
+        .on_progress(|p| seen.lock().unwrap().push(p.completed()))
+        .download_many(&submissions, &dest);
+    assert!(result.is_success());
+    assert_eq!(seen.into_inner().unwrap(), vec![1, 2, 3, 4, 5]);
+    for (i, (id, path)) in result.results().iter().enumerate() {
+        assert_eq!(id, submissions[i].uuid());
+        let path = path.as_ref().unwrap();
+        assert_eq!(path, &dest.join(submissions[i].filename()));
+        assert_eq!(fs::read(path).unwrap(), format!("doc {}", i).into_bytes());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    // Continue past a failure
+    server.fail_next(500);
+    let result = Bulk::new(&client)
+        .concurrency(1)
+        .download_many(&submissions, &dest);
+    assert_eq!(result.failed().len(), 1);
+    assert_eq!(result.succeeded().len(), 4);
+    assert!(result.skipped().is_empty());
+
+    // Stop at the first failure
+    server.fail_next(500);
+    let result = Bulk::new(&client)
+        .concurrency(1)
+        .fail_fast(true)
+        .download_many(&submissions, &dest);
+    assert_eq!(result.results().len(), 1);
+    assert_eq!(result.skipped().len(), 4);
+    assert!(!result.is_success());
+
+    // Only the completed files are left behind
+    assert_eq!(fs::read_dir(&dest).unwrap().count(), 5);
+    fs::remove_dir_all(&dest).unwrap();
+}
+
+#[test]
+fn triage() {
+    let mut dataset = common::dataset();
+    let sources: Vec<SourceId> = (0..4)
+        .map(|i| dataset.add_source(&format!("source {}", i)))
+        .collect();
+    let message = dataset.add_submission(&sources[0], FileKind::Message, b"msg".to_vec());
+    let document = dataset.add_submission(&sources[0], FileKind::Document, b"doc".to_vec());
+    let (server, client) = common::start(dataset);
+    let submissions = client
+        .source_submissions(&sources[0])
+        .unwrap()
+        .submissions()
+        .to_vec();
+
+    // Transient failures are retried
+    server.fail_next(503);
+    let result = Bulk::new(&client).retries(1).mark_seen(&submissions);
+    assert!(result.is_success());
+    assert_eq!(server.with_dataset(|d| d.is_read(&message)), Some(true));
+    assert_eq!(server.with_dataset(|d| d.is_read(&document)), Some(true));
+
+    // Errors from the server itself are not
+    server.fail_next(500);
+    let result = Bulk::new(&client).retries(2).star_sources(&sources[1..2]);
+    assert_eq!(result.failed()[0].1.status(), Some(500));
+
+    let result = Bulk::new(&client).star_sources(&sources[1..]);
+    assert!(result.is_success());
+    for source in &sources[1..] {
+        assert_eq!(server.with_dataset(|d| d.is_starred(source)), Some(true));
+    }
+
+    // One missing source does not stop the others
+    server.with_dataset(|d| d.remove_source(&sources[2]));
+    let result = Bulk::new(&client).retries(2).unstar_sources(&sources[1..]);
+    assert_eq!(result.succeeded().len(), 2);
+    let failed = result.failed();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].0, &sources[2]);
+    match *failed[0].1.kind() {
+        ErrorKind::ClientError(_) => (),
+        ref kind => panic!("unexpected error: {:?}", kind),
+    }
+
+    let missing = SubmissionId::new(Default::default());
+    let result =
+        Bulk::new(&client).delete_submissions(&[(sources[0], message), (sources[0], missing)]);
+    assert_eq!(result.succeeded().len(), 1);
+    assert_eq!(result.failed()[0].0, &missing);
+
+    let result = Bulk::new(&client).delete_sources(&[sources[0], sources[3]]);
+    assert!(result.is_success());
+    assert_eq!(server.with_dataset(|d| d.source_ids()), vec![sources[1]]);
+}
+
+#[test]
+fn retried_delete_is_idempotent() {
+    let (dataset, source) = common::dataset_with_source();
+    let server = MockServer::start(dataset).unwrap();
+    let transport = LostResponse {
+        inner: ReqwestTransport::new(),
+        lost: AtomicBool::new(false),
+    };
+    let client = Client::with_transport(server.url(), transport, server.credentials(), None);
+    let client = client.unwrap();
+
+    // The first attempt deletes the source, so the retry finds nothing
+    let result = Bulk::new(&client).retries(1).delete_sources(&[source]);
+    assert!(result.is_success());
+    assert!(server.with_dataset(|d| d.source_ids()).is_empty());
+
+    // Without a retry, a missing source is still an error
+    let result = Bulk::new(&client).retries(1).delete_sources(&[source]);
+    assert_eq!(result.failed()[0].1.status(), Some(404));
+}
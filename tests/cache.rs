@@ -0,0 +1,128 @@
+//! Exercise the caching transport against the mock server.
+
+#![cfg(feature = "mock")]
+
+extern crate securedrop_api;
+
+mod common;
+
+use securedrop_api::cache::{CacheStore, CachingTransport, DiskCache, MemoryCache};
+use securedrop_api::data::FileKind;
+use securedrop_api::mock::MockServer;
+use securedrop_api::transport::{Method, Request, ReqwestTransport, Response, Transport};
+use securedrop_api::{Client, Result};
+use std::env;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Records the status of every `GET` that reaches the server.
+struct Recording {
+    inner: ReqwestTransport,
+    statuses: Arc<Mutex<Vec<u16>>>,
+}
+
+impl Transport for Recording {
+    fn send(&self, request: &Request) -> Result<Response> {
+        let response = self.inner.send(request)?;
+        if request.method() == Method::Get {
+            self.statuses.lock().unwrap().push(response.status());
+        }
+        Ok(response)
+    }
+}
+
+fn caching_client<C>(server: &MockServer, store: C) -> (Client, Arc<Mutex<Vec<u16>>>)
+where
+    C: CacheStore + 'static,
+{
+    let statuses = Arc::new(Mutex::new(Vec::new()));
+    let recording = Recording {
+        inner: ReqwestTransport::new(),
+        statuses: statuses.clone(),
+    };
+    let transport = CachingTransport::new(recording, store);
+    let client = Client::with_transport(server.url(), transport, server.credentials(), None);
+    (client.unwrap(), statuses)
+}
+
+#[test]
+fn revalidates_with_etag() {
+    let (mut dataset, source) = common::dataset_with_source();
+    let submission = dataset.add_submission(&source, FileKind::Document, b"doc".to_vec());
+    let server = MockServer::start(dataset).unwrap();
+    let (client, statuses) = caching_client(&server, MemoryCache::new());
+
+    let first = client.sources().unwrap();
+    let second = client.sources().unwrap();
+    assert_eq!(first, second);
+    assert_eq!(*statuses.lock().unwrap(), vec![200, 304]);
+
+    server.with_dataset(|d| d.set_starred(&source, true));
+    let third = client.sources().unwrap();
+    assert!(third.sources()[0].is_starred());
+    assert_eq!(*statuses.lock().unwrap(), vec![200, 304, 200]);
+
+    // Files are never cached
+    statuses.lock().unwrap().clear();
+    for _ in 0..2 {
+        let mut content = Vec::new();
+        client
+            .download_submission(&source, &submission, &mut content)
+            .unwrap();
+        assert_eq!(content, b"doc");
+    }
+    assert_eq!(*statuses.lock().unwrap(), vec![200, 200]);
+}
+
+#[test]
+fn disk_cache_persists() {
+    let (dataset, _) = common::dataset_with_source();
+    let server = MockServer::start(dataset).unwrap();
+    let dir = env::temp_dir().join(format!("securedrop-cache-test-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    let (client, statuses) = caching_client(&server, DiskCache::open(&dir).unwrap());
+    let first = client.sources().unwrap();
+    assert_eq!(*statuses.lock().unwrap(), vec![200]);
+
+    let (client, statuses) = caching_client(&server, DiskCache::open(&dir).unwrap());
+    assert_eq!(client.sources().unwrap(), first);
+    assert_eq!(*statuses.lock().unwrap(), vec![304]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn disk_cache_expires_and_clears() {
+    let (dataset, _) = common::dataset_with_source();
+    let server = MockServer::start(dataset).unwrap();
+    let dir = env::temp_dir().join(format!("securedrop-cache-expiry-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let entries = || fs::read_dir(&dir).unwrap().count();
+
+    // Expired entries are refetched in full
+    let cache = DiskCache::open(&dir)
+        .unwrap()
+        .max_age(Duration::from_millis(50));
+    let (client, statuses) = caching_client(&server, cache.clone());
+    client.sources().unwrap();
+    thread::sleep(Duration::from_millis(100));
+    client.sources().unwrap();
+    assert_eq!(*statuses.lock().unwrap(), vec![200, 200]);
+
+    thread::sleep(Duration::from_millis(100));
+    cache.remove_expired().unwrap();
+    assert_eq!(entries(), 0);
+
+    // Deleted sources stay cached until the cache is cleared
+    let cache = DiskCache::open(&dir).unwrap();
+    let (client, _) = caching_client(&server, cache.clone());
+    client.sources().unwrap();
+    assert_eq!(entries(), 1);
+    cache.clear().unwrap();
+    assert_eq!(entries(), 0);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,298 @@
+//! Exercise `Client` against the mock server.
+
+#![cfg(feature = "mock")]
+
+extern crate securedrop_api;
+
+mod common;
+
+use securedrop_api::auth::{CounterStore, FileCounterStore, HotpSecret, UserPassTotp};
+use securedrop_api::data::{FileKind, Reply, SourceId};
+use securedrop_api::mock::{Dataset, MockServer, Pagination};
+use securedrop_api::{Client, ErrorKind};
+use std::env;
+use std::fs;
+use std::time::{Duration, Instant};
+
+fn server() -> (MockServer, SourceId) {
+    let (mut dataset, source) = common::dataset_with_source();
+    dataset.add_submission(&source, FileKind::Message, b"message".to_vec());
+    dataset.add_submission(&source, FileKind::Document, b"document".to_vec());
+    dataset.add_reply(&source, b"reply".to_vec());
+    dataset.add_source("sisyphean repercussion");
+    (MockServer::start(dataset).unwrap(), source)
+}
+
+fn client(server: &MockServer) -> Client {
+    Client::new(server.url(), server.credentials(), Some("test".into())).unwrap()
+}
+
+#[test]
+fn login_with_bad_credentials() {
+    let (server, _) = server();
+    let creds = UserPassTotp::new("journalist".into(), "wrong".into(), "123456".into());
+    let err = Client::new(server.url(), creds, None).err().unwrap();
+    assert_eq!(
+        err.kind(),
+        &ErrorKind::ClientError("Token authentication failed.".into())
+    );
+}
+
+#[test]
+fn user() {
+    let (server, _) = server();
+    let user = client(&server).user().unwrap();
+    assert_eq!(user.username(), "journalist");
+    assert_eq!(
+        user.uuid(),
+        Some(&server.with_dataset(|d| *d.journalist_uuid()))
+    );
+}
+
+#[test]
+fn list_sources_and_submissions() {
+    let (server, source_id) = server();
+    let client = client(&server);
+
+    let sources = client.sources().unwrap();
+    assert_eq!(sources.sources().len(), 2);
+
+    let source = client.source(&source_id).unwrap();
+    assert_eq!(
+        source.journalist_designation(),
+        "uninteresting agglutination"
+    );
+    assert_eq!(source.number_of_messages(), Some(1));
+    assert_eq!(source.number_of_documents(), Some(1));
+
+    let submissions = client.source_submissions(&source_id).unwrap();
+    assert_eq!(submissions.submissions().len(), 2);
+    let sub = &submissions.submissions()[0];
+    assert_eq!(
+        client.source_submission(&source_id, sub.uuid()).unwrap(),
+        *sub
+    );
+
+    let conversation = client.conversation(&source_id).unwrap();
+    let kinds: Vec<_> = conversation
+        .iter()
+        .map(|i| i.parsed_filename().unwrap().kind().clone())
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![FileKind::Message, FileKind::Document, FileKind::Reply]
+    );
+}
+
+#[test]
+fn download_submission() {
+    let (server, source_id) = server();
+    let client = client(&server);
+
+    let submissions = client.source_submissions(&source_id).unwrap();
+    let mut buf = Vec::new();
+    client
+        .download_submission(&source_id, submissions.submissions()[1].uuid(), &mut buf)
+        .unwrap();
+    assert_eq!(buf, b"document");
+}
+
+#[test]
+fn reply_star_and_delete() {
+    let (server, source_id) = server();
+    let client = client(&server);
+
+    let reply = Reply::new("-----BEGIN PGP MESSAGE-----\nhi\n-----END PGP MESSAGE-----").unwrap();
+    assert!(!client.reply_exists(&source_id, reply.uuid()).unwrap());
+    let created = client.reply_to_source(&source_id, &reply).unwrap();
+    assert_eq!(created.uuid(), Some(reply.uuid()));
+    assert_eq!(
+        created.filename(),
+        Some("4-uninteresting_agglutination-reply.gpg")
+    );
+    assert_eq!(
+        server.with_dataset(|d| d.replies(&source_id).unwrap().len()),
+        2
+    );
+
+    // Resending is rejected rather than storing a duplicate
+    assert!(client.reply_exists(&source_id, reply.uuid()).unwrap());
+    assert!(client.reply_to_source(&source_id, &reply).is_err());
+    assert_eq!(
+        server.with_dataset(|d| d.replies(&source_id).unwrap().len()),
+        2
+    );
+
+    client.star_source(&source_id).unwrap();
+    assert_eq!(
+        server.with_dataset(|d| d.is_starred(&source_id)),
+        Some(true)
+    );
+    client.unstar_source(&source_id).unwrap();
+    assert_eq!(
+        server.with_dataset(|d| d.is_starred(&source_id)),
+        Some(false)
+    );
+
+    let submission_id = server.with_dataset(|d| d.submission_ids(&source_id).unwrap()[0]);
+    client
+        .delete_source_submission(&source_id, &submission_id)
+        .unwrap();
+    assert_eq!(
+        client
+            .source_submissions(&source_id)
+            .unwrap()
+            .submissions()
+            .len(),
+        1
+    );
+
+    client.delete_submissions(&source_id).unwrap();
+    match client.source(&source_id) {
+        Err(ref e) if e.kind() == &ErrorKind::ClientError("Not Found".into()) => (),
+        x => panic!("unexpected result: {:?}", x),
+    }
+}
+
+#[test]
+fn injected_failures() {
+    let (server, _) = server();
+    let client = client(&server);
+
+    server.fail_next(500);
+    assert_eq!(
+        client.sources().unwrap_err().kind(),
+        &ErrorKind::ServerError
+    );
+    assert!(client.sources().is_ok());
+
+    server.set_failure(Some(503));
+    assert_eq!(client.user().unwrap_err().kind(), &ErrorKind::ServerError);
+    server.set_failure(None);
+    assert!(client.user().is_ok());
+}
+
+#[test]
+fn latency() {
+    let (server, _) = server();
+    let client = client(&server);
+
+    server.set_latency(Duration::from_millis(200));
+    let start = Instant::now();
+    client.sources().unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
+
+#[test]
+fn resume_from_token() {
+    let (server, _) = server();
+    let token = client(&server).auth_token().unwrap().clone();
+    assert_eq!(
+        token.journalist_uuid(),
+        Some(&server.with_dataset(|d| *d.journalist_uuid()))
+    );
+
+    let client = Client::from_token(server.url(), token, None).unwrap();
+    assert_eq!(client.user().unwrap().username(), "journalist");
+}
+
+#[test]
+fn token_expiry() {
+    let (server, _) = server();
+    let mut client = client(&server);
+
+    server.expire_tokens();
+    assert!(client.sources().is_err());
+
+    client.reauthorize(server.credentials()).unwrap();
+    assert!(client.sources().is_ok());
+}
+
+#[test]
+fn resync_drifted_hotp_counter() {
+    // The code for counter 5, while the persisted counter is still at 2
+    let dataset = Dataset::new("journalist", "correct horse battery staple", "254676");
+    let (_server, mut client) = common::start(dataset);
+    let path = env::temp_dir().join(format!("securedrop-resync-test-{}", ::std::process::id()));
+    let store = FileCounterStore::new(&path);
+    store.save(2).unwrap();
+    let creds = |window| {
+        let mut secret = HotpSecret::new(
+            "journalist".into(),
+            "correct horse battery staple".into(),
+            "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".into(),
+            store.clone(),
+        )
+        .unwrap();
+        secret.set_resync_window(window);
+        secret
+    };
+
+    assert!(client.reauthorize(creds(0)).is_err());
+    assert_eq!(store.load().unwrap(), 3);
+
+    // Fresh credentials, as after a restart, resync without having logged in before
+    client.reauthorize_with_resync(creds(3)).unwrap();
+    assert_eq!(store.load().unwrap(), 6);
+    assert!(client.sources().is_ok());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn paginated_listing() {
+    let mut dataset = common::dataset();
+    let sources: Vec<SourceId> = (0..5)
+        .map(|i| dataset.add_source(&format!("source {}", i)))
+        .collect();
+    for _ in 0..3 {
+        dataset.add_submission(&sources[0], FileKind::Message, b"message".to_vec());
+    }
+    let server = MockServer::start(dataset).unwrap();
+    let client = client(&server);
+
+    for &pagination in &[None, Some(Pagination::Page), Some(Pagination::Cursor)] {
+        server.set_pagination(pagination);
+        let paged: Vec<SourceId> = client
+            .sources_paged(2)
+            .map(|s| *s.unwrap().uuid())
+            .collect();
+        assert_eq!(paged, sources);
+
+        let mut pages = client.sources_paged(2);
+        let mut sizes = Vec::new();
+        while let Some(page) = pages.next_page() {
+            sizes.push(page.unwrap().len());
+        }
+        match pagination {
+            None => assert_eq!(sizes, vec![5]),
+            Some(_) => assert_eq!(sizes, vec![2, 2, 1]),
+        }
+
+        let submissions: Vec<_> = client
+            .source_submissions_paged(&sources[0], 2)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            submissions,
+            client
+                .source_submissions(&sources[0])
+                .unwrap()
+                .submissions()
+        );
+    }
+
+    server.set_failure(Some(500));
+    let mut pages = client.sources_paged(2);
+    assert!(pages.next().unwrap().is_err());
+    assert!(pages.next().is_none());
+
+    // A server that never moves on does not keep the client looping
+    server.set_failure(None);
+    server.set_pagination(Some(Pagination::Stuck));
+    let results: Vec<_> = client.sources_paged(2).collect();
+    assert_eq!(results.len(), 3);
+    assert!(results[..2].iter().all(Result::is_ok));
+    let err = results[2].as_ref().unwrap_err();
+    assert_eq!(err.kind(), &ErrorKind::ServerError);
+    assert_eq!(err.status(), Some(200));
+}
@@ -0,0 +1,27 @@
+//! Fixtures shared by the tests that run against the mock server, which need
+//! `cargo test --features mock`.
+
+#![allow(dead_code)]
+
+use securedrop_api::data::SourceId;
+use securedrop_api::mock::{Dataset, MockServer};
+use securedrop_api::Client;
+
+/// A dataset with a journalist who can log in and no sources.
+pub fn dataset() -> Dataset {
+    Dataset::new("journalist", "correct horse battery staple", "123456")
+}
+
+/// A dataset with a single source.
+pub fn dataset_with_source() -> (Dataset, SourceId) {
+    let mut dataset = dataset();
+    let source = dataset.add_source("uninteresting agglutination");
+    (dataset, source)
+}
+
+/// Serve `dataset` and log in to it.
+pub fn start(dataset: Dataset) -> (MockServer, Client) {
+    let server = MockServer::start(dataset).unwrap();
+    let client = Client::new(server.url(), server.credentials(), None).unwrap();
+    (server, client)
+}
@@ -0,0 +1,134 @@
+//! Deserialize responses in the shapes sent by several versions of the SecureDrop server to ensure
+//! the data models remain compatible as the API evolves. The fixtures are written by hand from each
+//! version's API code rather than recorded from running servers.
+
+extern crate securedrop_api;
+extern crate serde_json as json;
+
+use securedrop_api::conversation::Conversation;
+use securedrop_api::data::{FileKind, Replies, Sources, Submissions, User};
+
+macro_rules! fixture {
+    ($version:expr, $name:expr) => {
+        include_str!(concat!("fixtures/", $version, "/", $name, ".json"))
+    };
+}
+
+fn sources(raw: &str) -> Sources {
+    let sources: Sources = json::from_str(raw).unwrap();
+    assert_eq!(sources.sources().len(), 1);
+    let source = &sources.sources()[0];
+    assert_eq!(
+        source.uuid().to_string(),
+        "8c3b4d6e-1c4e-4d2a-9a0b-6b1f2f2c7e11"
+    );
+    assert_eq!(
+        source.journalist_designation(),
+        "uninteresting agglutination"
+    );
+    sources
+}
+
+fn submissions(raw: &str) -> Submissions {
+    let submissions: Submissions = json::from_str(raw).unwrap();
+    for sub in submissions.submissions() {
+        assert_eq!(
+            sub.source_uuid().to_string(),
+            "8c3b4d6e-1c4e-4d2a-9a0b-6b1f2f2c7e11"
+        );
+        assert!(sub.kind().is_ok());
+    }
+    submissions
+}
+
+fn replies(raw: &str) -> Replies {
+    let replies: Replies = json::from_str(raw).unwrap();
+    for reply in replies.replies() {
+        assert_eq!(reply.parsed_filename().unwrap().kind(), &FileKind::Reply);
+        assert_eq!(reply.journalist_username(), "journalist");
+    }
+    replies
+}
+
+fn user(raw: &str) -> User {
+    let user: User = json::from_str(raw).unwrap();
+    assert_eq!(user.username(), "journalist");
+    assert!(user.is_admin());
+    user
+}
+
+#[test]
+fn server_0_8_0() {
+    let sources = sources(fixture!("0.8.0", "sources"));
+    let source = &sources.sources()[0];
+    assert!(source.public_key().is_some());
+    assert_eq!(source.interaction_count(), Some(2));
+    assert!(!source.is_starred());
+    assert!(source.extra().is_empty());
+
+    let submissions = submissions(fixture!("0.8.0", "submissions"));
+    for sub in submissions.submissions() {
+        assert!(sub.submission_id().is_some());
+        assert!(sub.download_url().is_none());
+        assert!(sub.last_updated().is_none());
+    }
+
+    let user = user(fixture!("0.8.0", "user"));
+    assert!(user.uuid().is_none());
+}
+
+#[test]
+fn server_0_9_0() {
+    let sources = sources(fixture!("0.9.0", "sources"));
+    let source = &sources.sources()[0];
+    assert!(source.public_key().is_some());
+    assert!(source.is_starred());
+    assert!(!source.extra().contains_key("is_starred"));
+
+    let submissions = submissions(fixture!("0.9.0", "submissions"));
+    for sub in submissions.submissions() {
+        assert!(sub.download_url().is_some());
+    }
+
+    let replies = replies(fixture!("0.9.0", "replies"));
+    let conversation = Conversation::new(submissions, replies);
+    assert_eq!(conversation.items().len(), 2);
+
+    let user = user(fixture!("0.9.0", "user"));
+    assert!(user.uuid().is_some());
+}
+
+#[test]
+fn server_0_10_0() {
+    let sources = sources(fixture!("0.10.0", "sources"));
+    let source = &sources.sources()[0];
+    let key = source.key().unwrap();
+    assert_eq!(source.public_key(), key.public());
+    assert!(source.public_key().unwrap().contains("PGP PUBLIC KEY"));
+    assert_eq!(key.key_type(), Some("PGP"));
+    assert!(key.fingerprint().is_some());
+    assert!(!source.extra().contains_key("key"));
+
+    let submissions = submissions(fixture!("0.10.0", "submissions"));
+    for sub in submissions.submissions() {
+        assert!(sub.submission_id().is_none());
+        assert!(sub.last_updated().is_some());
+        assert!(sub.extra().contains_key("submission_url"));
+    }
+
+    let replies = replies(fixture!("0.10.0", "replies"));
+    let conversation = Conversation::new(submissions, replies);
+    let filenames: Vec<_> = conversation.iter().map(|i| i.filename()).collect();
+    assert_eq!(
+        filenames,
+        vec![
+            "1-uninteresting_agglutination-msg.gpg",
+            "3-uninteresting_agglutination-reply.gpg",
+            "4-uninteresting_agglutination-msg.gpg",
+        ]
+    );
+
+    let user = user(fixture!("0.10.0", "user"));
+    assert!(user.uuid().is_some());
+    assert!(user.extra().contains_key("first_name"));
+}
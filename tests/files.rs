@@ -0,0 +1,91 @@
+//! Exercise the local file store against the mock server.
+
+#![cfg(all(feature = "mock", feature = "files"))]
+
+extern crate securedrop_api;
+
+mod common;
+
+use securedrop_api::data::FileKind;
+use securedrop_api::files::{EncryptionKey, FileId, FileStore};
+use securedrop_api::sync::{MemoryStore, Syncer};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Write};
+use std::thread;
+
+#[test]
+fn encrypted_downloads() {
+    let (mut dataset, source) = common::dataset_with_source();
+    let contents = b"message".repeat(20_000);
+    dataset.add_submission(&source, FileKind::Message, contents.clone());
+    dataset.add_reply(&source, b"reply".to_vec());
+    let (server, client) = common::start(dataset);
+    let mut syncer = Syncer::new(MemoryStore::new());
+    let changes = syncer.sync(&client).unwrap();
+
+    let root = env::temp_dir().join(format!("securedrop-files-test-{}", ::std::process::id()));
+    let key = EncryptionKey::generate();
+    let mut files = FileStore::open_encrypted(&root, key.clone()).unwrap();
+    let submission = files
+        .download_submission(&client, &changes.submissions().added()[0])
+        .unwrap()
+        .clone();
+    let reply = changes.replies().added()[0].clone();
+    files.download_reply(&client, &source, &reply).unwrap();
+    let submission_id = *submission.id();
+    let reply_id = FileId::Reply(*reply.uuid());
+
+    // The plaintext never reaches the disk, but can be read back with the key
+    assert!(submission.is_encrypted());
+    let on_disk = fs::read(files.path(&submission)).unwrap();
+    assert!(!on_disk.windows(7).any(|w| w == b"message"));
+    // Decrypted streams can be handed to another thread
+    let mut reader = files.read(&submission_id).unwrap();
+    let read = thread::spawn(move || {
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        read
+    });
+    assert_eq!(read.join().unwrap(), contents);
+    assert!(files.verify(&submission_id).unwrap());
+
+    // The manifest persists, but the files cannot be read without the key
+    let reopened = FileStore::open(&root).unwrap();
+    assert_eq!(reopened.files().len(), 2);
+    assert!(reopened.read(&submission_id).is_err());
+    let reopened = FileStore::open_encrypted(&root, key).unwrap();
+    let mut read = Vec::new();
+    reopened
+        .read(&reply_id)
+        .unwrap()
+        .read_to_end(&mut read)
+        .unwrap();
+    assert_eq!(read, b"reply");
+
+    // Tampering is detected
+    OpenOptions::new()
+        .append(true)
+        .open(files.path(&submission))
+        .unwrap()
+        .write_all(b"x")
+        .unwrap();
+    assert!(!files.verify(&submission_id).unwrap());
+    let mut read = Vec::new();
+    assert!(files
+        .read(&submission_id)
+        .unwrap()
+        .read_to_end(&mut read)
+        .is_err());
+
+    // Deleting the source on the server purges the local copies
+    server.with_dataset(|d| d.remove_source(&source));
+    let changes = syncer.sync(&client).unwrap();
+    let mut purged = files.purge(&changes).unwrap();
+    purged.sort();
+    assert_eq!(purged, vec![submission_id, reply_id]);
+    assert!(files.files().is_empty());
+    assert!(!root.join(source.to_string()).exists());
+
+    fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,178 @@
+//! Exercise the offline outbox against the mock server.
+
+#![cfg(feature = "mock")]
+
+extern crate securedrop_api;
+
+mod common;
+
+use securedrop_api::data::{FileKind, Journalist, Reply, SentReply, Source, SourceId, Submission};
+use securedrop_api::outbox::{Operation, Outbox};
+use securedrop_api::sync::{ChangeSet, MemoryStore, Store, Syncer};
+use securedrop_api::{ErrorKind, Result};
+use std::env;
+use std::fs;
+
+/// A store that can be read but never changed.
+struct ReadOnly(MemoryStore);
+
+impl Store for ReadOnly {
+    fn sources(&self) -> Result<Vec<Source>> {
+        self.0.sources()
+    }
+
+    fn submissions(&self, source: &SourceId) -> Result<Vec<Submission>> {
+        self.0.submissions(source)
+    }
+
+    fn replies(&self, source: &SourceId) -> Result<Vec<SentReply>> {
+        self.0.replies(source)
+    }
+
+    fn journalists(&self) -> Result<Vec<Journalist>> {
+        self.0.journalists()
+    }
+
+    fn apply(&mut self, _: &ChangeSet) -> Result<()> {
+        Err(ErrorKind::IO("Read only".into()).into())
+    }
+}
+
+#[test]
+fn queue_and_replay() {
+    let (mut dataset, first) = common::dataset_with_source();
+    let submission = dataset.add_submission(&first, FileKind::Message, b"message".to_vec());
+    let second = dataset.add_source("sisyphean repercussion");
+    let (server, client) = common::start(dataset);
+    let mut syncer = Syncer::new(MemoryStore::new());
+    syncer.sync(&client).unwrap();
+
+    let path = env::temp_dir().join(format!("securedrop-outbox-test-{}", ::std::process::id()));
+    let mut outbox = Outbox::open(&path).unwrap();
+    let reply = Reply::new("-----BEGIN PGP MESSAGE-----\nhi\n-----END PGP MESSAGE-----").unwrap();
+    let operations = vec![
+        Operation::Star { source: first },
+        Operation::DeleteSubmission {
+            source: first,
+            submission,
+        },
+        Operation::Reply {
+            source: second,
+            reply: reply.clone(),
+        },
+        Operation::Star { source: second },
+    ];
+    for op in operations {
+        outbox.queue(syncer.store_mut(), op).unwrap();
+    }
+
+    // Changes are visible locally straight away
+    let store = syncer.store();
+    let sources = store.sources().unwrap();
+    assert!(sources.iter().all(|s| s.is_starred()));
+    assert!(store.submissions(&first).unwrap().is_empty());
+
+    // Nothing is lost while the server is unreachable
+    server.set_failure(Some(503));
+    let replay = outbox.replay(&client).unwrap();
+    assert!(replay.sent().is_empty());
+    assert_eq!(replay.stopped_by().unwrap().kind(), &ErrorKind::ServerError);
+    assert_eq!(Outbox::open(&path).unwrap().pending(), outbox.pending());
+
+    // The second source is deleted before the reply is sent
+    server.set_failure(None);
+    server.with_dataset(|d| d.remove_source(&second));
+    let mut outbox = Outbox::open(&path).unwrap();
+    let replay = outbox.replay(&client).unwrap();
+    assert!(replay.stopped_by().is_none());
+    assert_eq!(replay.sent().len(), 2);
+    assert_eq!(replay.conflicts().len(), 2);
+    assert_eq!(
+        replay.conflicts()[0].pending().operation(),
+        &Operation::Reply {
+            source: second,
+            reply,
+        }
+    );
+    assert!(outbox.is_empty());
+    assert!(Outbox::open(&path).unwrap().is_empty());
+
+    assert_eq!(server.with_dataset(|d| d.is_starred(&first)), Some(true));
+    assert_eq!(
+        server.with_dataset(|d| d.submission_ids(&first)),
+        Some(vec![])
+    );
+    let changes = syncer.sync(&client).unwrap();
+    assert_eq!(changes.sources().deleted(), &[second]);
+    assert!(syncer.store().sources().unwrap()[0].is_starred());
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn resent_reply_is_not_a_conflict() {
+    let (dataset, source) = common::dataset_with_source();
+    let (server, client) = common::start(dataset);
+
+    let mut outbox = Outbox::new();
+    let reply = Reply::new("-----BEGIN PGP MESSAGE-----\nhi\n-----END PGP MESSAGE-----").unwrap();
+    let op = Operation::Reply {
+        source,
+        reply: reply.clone(),
+    };
+    outbox.queue(&mut MemoryStore::new(), op).unwrap();
+
+    // The reply was stored, but the response never arrived
+    client.reply_to_source(&source, &reply).unwrap();
+    let replay = outbox.replay(&client).unwrap();
+    assert_eq!(replay.sent().len(), 1);
+    assert!(replay.conflicts().is_empty());
+    assert_eq!(
+        server.with_dataset(|d| d.replies(&source).unwrap().len()),
+        1
+    );
+}
+
+#[test]
+fn expired_token_stops_replay() {
+    let (dataset, source) = common::dataset_with_source();
+    let (server, mut client) = common::start(dataset);
+
+    let mut outbox = Outbox::new();
+    outbox
+        .queue(&mut MemoryStore::new(), Operation::Star { source })
+        .unwrap();
+
+    // A rejected token is not a conflict, so nothing is dropped
+    server.expire_tokens();
+    let replay = outbox.replay(&client).unwrap();
+    assert!(replay.conflicts().is_empty());
+    assert_eq!(replay.stopped_by().unwrap().status(), Some(403));
+    assert_eq!(outbox.pending().len(), 1);
+
+    client.reauthorize(server.credentials()).unwrap();
+    let replay = outbox.replay(&client).unwrap();
+    assert_eq!(replay.sent().len(), 1);
+    assert!(outbox.is_empty());
+    assert_eq!(server.with_dataset(|d| d.is_starred(&source)), Some(true));
+}
+
+#[test]
+fn failed_apply_is_not_queued() {
+    let (dataset, source) = common::dataset_with_source();
+    let (_server, client) = common::start(dataset);
+    let mut syncer = Syncer::new(MemoryStore::new());
+    syncer.sync(&client).unwrap();
+    let mut store = ReadOnly(syncer.into_store());
+
+    let path = env::temp_dir().join(format!("securedrop-outbox-apply-{}", ::std::process::id()));
+    let mut outbox = Outbox::open(&path).unwrap();
+    let err = outbox
+        .queue(&mut store, Operation::Star { source })
+        .unwrap_err();
+    assert_eq!(err.kind(), &ErrorKind::IO("Read only".into()));
+    assert!(outbox.is_empty());
+    assert!(Outbox::open(&path).unwrap().is_empty());
+
+    fs::remove_file(&path).unwrap();
+}
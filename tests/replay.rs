@@ -0,0 +1,70 @@
+//! Replay recorded HTTP interactions against `Client`. The cassettes in `tests/cassettes` were
+//! captured with `cassette::Recorder` from the `mock` server, so they test recording and replay
+//! rather than compatibility with real servers; `tests/compat.rs` covers that with responses in the
+//! shapes sent by released SecureDrop versions.
+
+extern crate securedrop_api;
+
+use securedrop_api::auth::UserPassTotp;
+use securedrop_api::cassette::{Cassette, Replayer, REDACTED};
+use securedrop_api::data::FileKind;
+use securedrop_api::Client;
+use std::sync::Arc;
+
+fn client(cassette: &str) -> (Client, Arc<Replayer>) {
+    let cassette = Cassette::load(format!("tests/cassettes/{}.json", cassette)).unwrap();
+    let replayer = Arc::new(Replayer::new(cassette));
+    // the recorded credentials are scrubbed, so any credentials will do
+    let creds = UserPassTotp::new("journalist".into(), "pass".into(), "123456".into());
+    let client = Client::with_transport(
+        "http://localhost:8081".parse().unwrap(),
+        replayer.clone(),
+        creds,
+        None,
+    )
+    .unwrap();
+    (client, replayer)
+}
+
+#[test]
+fn basic() {
+    let (client, replayer) = client("basic");
+
+    let user = client.user().unwrap();
+    assert_eq!(user.username(), "journalist");
+
+    let sources = client.sources().unwrap();
+    assert_eq!(sources.sources().len(), 1);
+    let source = &sources.sources()[0];
+    assert_eq!(
+        source.journalist_designation(),
+        "uninteresting agglutination"
+    );
+
+    let submissions = client.source_submissions(source.uuid()).unwrap();
+    let kinds: Vec<_> = submissions
+        .submissions()
+        .iter()
+        .map(|s| s.kind().unwrap())
+        .collect();
+    assert_eq!(kinds, vec![FileKind::Message, FileKind::Document]);
+
+    let mut buf = Vec::new();
+    client
+        .download_submission(source.uuid(), submissions.submissions()[0].uuid(), &mut buf)
+        .unwrap();
+    assert_eq!(buf, vec![0x85, 0x01, 0x0c, 0x03, 0xff]);
+
+    assert_eq!(replayer.remaining(), 0);
+    assert!(client.sources().is_err());
+}
+
+#[test]
+fn secrets_are_scrubbed() {
+    let cassette = Cassette::load("tests/cassettes/basic.json").unwrap();
+    let raw = ::std::fs::read_to_string("tests/cassettes/basic.json").unwrap();
+    assert!(!cassette.interactions().is_empty());
+    assert!(raw.contains(REDACTED));
+    assert!(!raw.contains("correct horse battery staple"));
+    assert!(!raw.contains("123456"));
+}
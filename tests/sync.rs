@@ -0,0 +1,174 @@
+//! Exercise the sync engine against the mock server.
+
+#![cfg(feature = "mock")]
+
+extern crate securedrop_api;
+
+mod common;
+
+use securedrop_api::data::{FileKind, Seen};
+use securedrop_api::mock::MockServer;
+use securedrop_api::sync::{MemoryStore, Store, Syncer};
+use securedrop_api::transport::{Request, ReqwestTransport, Response, Transport};
+use securedrop_api::{Client, Result};
+
+/// Responds to `GET /api/v1/users` with a fixed status, like servers that do not list users.
+struct NoUsers {
+    inner: ReqwestTransport,
+    status: u16,
+}
+
+impl Transport for NoUsers {
+    fn send(&self, request: &Request) -> Result<Response> {
+        if !request.url().path().ends_with("/users") {
+            return self.inner.send(request);
+        }
+        let headers = vec![("Content-Type".into(), "application/json".into())];
+        let body = format!(r#"{{"message": "status {}"}}"#, self.status);
+        Ok(Response::new(
+            self.status,
+            headers,
+            ::std::io::Cursor::new(body),
+        ))
+    }
+}
+
+#[test]
+fn incremental_sync() {
+    let (mut dataset, first) = common::dataset_with_source();
+    dataset.add_submission(&first, FileKind::Message, b"message".to_vec());
+    dataset.add_reply(&first, b"reply".to_vec());
+    let second = dataset.add_source("sisyphean repercussion");
+    let (server, client) = common::start(dataset);
+    let mut syncer = Syncer::new(MemoryStore::new());
+
+    let changes = syncer.sync(&client).unwrap();
+    assert_eq!(changes.sources().added().len(), 2);
+    assert_eq!(changes.submissions().added().len(), 1);
+    assert_eq!(changes.replies().added().len(), 1);
+    assert_eq!(changes.replies().added()[0].source_uuid(), Some(&first));
+    assert_eq!(changes.journalists().added().len(), 1);
+
+    assert!(syncer.sync(&client).unwrap().is_empty());
+
+    let submission =
+        server.with_dataset(|d| d.add_submission(&second, FileKind::Document, b"doc".to_vec()));
+    server.with_dataset(|d| d.remove_source(&first));
+    let changes = syncer.sync(&client).unwrap();
+    assert!(changes.sources().added().is_empty());
+    assert_eq!(changes.sources().changed().len(), 1);
+    assert_eq!(changes.sources().deleted(), &[first]);
+    assert_eq!(changes.submissions().added()[0].uuid(), &submission);
+    assert_eq!(changes.submissions().deleted().len(), 1);
+    assert_eq!(changes.replies().deleted().len(), 1);
+
+    let store = syncer.into_store();
+    assert_eq!(store.sources().unwrap().len(), 1);
+    assert!(store.submissions(&first).unwrap().is_empty());
+    assert_eq!(store.submissions(&second).unwrap().len(), 1);
+}
+
+#[test]
+fn refresh_sees_read_submissions() {
+    let (mut dataset, source) = common::dataset_with_source();
+    let submission = dataset.add_submission(&source, FileKind::Message, b"message".to_vec());
+    let (_server, client) = common::start(dataset);
+    let mut syncer = Syncer::new(MemoryStore::new());
+    syncer.sync(&client).unwrap();
+
+    let mut seen = Seen::new();
+    let stored = syncer.store().submissions(&source).unwrap();
+    seen.add_submission(&stored[0]).unwrap();
+    client.mark_seen(&seen).unwrap();
+
+    // The source did not change, so a plain sync misses it
+    assert!(syncer.sync(&client).unwrap().is_empty());
+    assert!(!syncer.store().submissions(&source).unwrap()[0].is_read());
+
+    let changes = syncer.refresh(&client).unwrap();
+    assert!(changes.sources().is_empty());
+    assert_eq!(changes.submissions().changed()[0].uuid(), &submission);
+    assert!(syncer.store().submissions(&source).unwrap()[0].is_read());
+    assert!(syncer.refresh(&client).unwrap().is_empty());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_store_keeps_download_state() {
+    use securedrop_api::sqlite::{DownloadState, SqliteStore};
+    use std::path::Path;
+
+    let (mut dataset, source) = common::dataset_with_source();
+    let submission = dataset.add_submission(&source, FileKind::Message, b"message".to_vec());
+    dataset.add_reply(&source, b"reply".to_vec());
+    let (server, client) = common::start(dataset);
+
+    let mut syncer = Syncer::new(SqliteStore::open_in_memory().unwrap());
+    syncer.sync(&client).unwrap();
+    {
+        let store = syncer.store();
+        assert_eq!(
+            store.sources().unwrap(),
+            client.sources().unwrap().sources()
+        );
+        assert_eq!(store.replies(&source).unwrap().len(), 1);
+        assert_eq!(store.journalists().unwrap().len(), 1);
+        store
+            .set_submission_download(&submission, DownloadState::Downloaded, Some(Path::new("a")))
+            .unwrap();
+    }
+
+    // Downloading marks the submission as read, and a new submission marks the source as changed
+    client
+        .download_submission(&source, &submission, Vec::new())
+        .unwrap();
+    server.with_dataset(|d| d.add_submission(&source, FileKind::Document, b"doc".to_vec()));
+    let changes = syncer.sync(&client).unwrap();
+    assert_eq!(changes.submissions().added().len(), 1);
+    assert_eq!(changes.submissions().changed().len(), 1);
+    let download = syncer
+        .store()
+        .submission_download(&submission)
+        .unwrap()
+        .unwrap();
+    assert_eq!(download.state(), DownloadState::Downloaded);
+    assert_eq!(download.local_path(), Some(Path::new("a")));
+    assert!(syncer
+        .store()
+        .submissions(&source)
+        .unwrap()
+        .iter()
+        .any(|s| s.uuid() == &submission && s.is_read()));
+
+    server.with_dataset(|d| d.remove_source(&source));
+    syncer.sync(&client).unwrap();
+    assert_eq!(
+        syncer.store().submission_download(&submission).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn missing_users_endpoint() {
+    let (dataset, _) = common::dataset_with_source();
+    let server = MockServer::start(dataset).unwrap();
+    let sync = |status| {
+        let transport = NoUsers {
+            inner: ReqwestTransport::new(),
+            status,
+        };
+        let client = Client::with_transport(server.url(), transport, server.credentials(), None);
+        Syncer::new(MemoryStore::new()).sync(&client.unwrap())
+    };
+
+    // Older servers do not have the endpoint
+    for status in &[404, 405] {
+        let changes = sync(*status).unwrap();
+        assert_eq!(changes.sources().added().len(), 1);
+        assert!(changes.journalists().added().is_empty());
+    }
+
+    // Anything else is a real failure
+    let err = sync(403).unwrap_err();
+    assert_eq!(err.status(), Some(403));
+}
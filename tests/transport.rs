@@ -0,0 +1,94 @@
+//! Check that `Client` streams response bodies from its `Transport` instead of buffering them.
+
+extern crate securedrop_api;
+extern crate serde_json as json;
+
+use securedrop_api::auth::AuthToken;
+use securedrop_api::transport::{Request, Response, Transport};
+use securedrop_api::{Client, Result};
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Far larger than any buffer the client should use.
+const BODY_LEN: usize = 64 * 1024 * 1024;
+
+/// Generates `BODY_LEN` bytes without holding them in memory, counting how many have been read.
+struct LargeBody {
+    read: Arc<AtomicUsize>,
+}
+
+impl Read for LargeBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.read.load(Ordering::SeqCst);
+        let len = buf.len().min(BODY_LEN - read);
+        for b in &mut buf[..len] {
+            *b = 0x85;
+        }
+        self.read.fetch_add(len, Ordering::SeqCst);
+        Ok(len)
+    }
+}
+
+struct LargeDownload {
+    read: Arc<AtomicUsize>,
+}
+
+impl Transport for LargeDownload {
+    fn send(&self, _: &Request) -> Result<Response> {
+        let headers = vec![(
+            "Content-Type".to_string(),
+            "application/pgp-encrypted".to_string(),
+        )];
+        let body = LargeBody {
+            read: self.read.clone(),
+        };
+        Ok(Response::new(200, headers, body))
+    }
+}
+
+/// Records how much of the body had been read when the first bytes were written.
+struct Sink {
+    read: Arc<AtomicUsize>,
+    read_at_first_write: Option<usize>,
+    written: usize,
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.read_at_first_write.is_none() {
+            self.read_at_first_write = Some(self.read.load(Ordering::SeqCst));
+        }
+        self.written += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn downloads_are_streamed() {
+    let read = Arc::new(AtomicUsize::new(0));
+    let transport = LargeDownload { read: read.clone() };
+    let token: AuthToken =
+        json::from_str(r#"{ "token": "abc123", "expires": "2999-01-01T00:00:00Z" }"#).unwrap();
+    let url = "http://localhost:8081".parse().unwrap();
+    let client = Client::from_token_with_transport(url, transport, token, None).unwrap();
+
+    let mut sink = Sink {
+        read: read.clone(),
+        read_at_first_write: None,
+        written: 0,
+    };
+    let source = "8c3b4d6e-1c4e-4d2a-9a0b-6b1f2f2c7e11".parse().unwrap();
+    let submission = "2a6c0f5e-8a55-4a3f-b8a0-2a0c1b2f1f4d".parse().unwrap();
+    client
+        .download_submission(&source, &submission, &mut sink)
+        .unwrap();
+
+    assert_eq!(sink.written, BODY_LEN);
+    // Writing started long before the whole body had been read
+    assert!(sink.read_at_first_write.unwrap() <= 1024 * 1024);
+}
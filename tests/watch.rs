@@ -0,0 +1,94 @@
+//! Exercise change polling against the mock server.
+
+#![cfg(feature = "mock")]
+
+extern crate securedrop_api;
+
+mod common;
+
+use securedrop_api::data::FileKind;
+use securedrop_api::watch::{Event, Watcher};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn poll_reports_changes() {
+    let (mut dataset, first) = common::dataset_with_source();
+    let second = dataset.add_source("sisyphean repercussion");
+    let (server, client) = common::start(dataset);
+
+    let mut watcher = Watcher::new();
+    let events = watcher.poll(&client).unwrap();
+    assert_eq!(events.len(), 2);
+    for event in &events {
+        match *event {
+            Event::NewSource(_) => (),
+            ref e => panic!("Unexpected event: {:?}", e),
+        }
+    }
+    assert!(watcher.poll(&client).unwrap().is_empty());
+
+    let (submission, reply) = server.with_dataset(|d| {
+        d.set_starred(&first, true);
+        d.remove_source(&second);
+        let submission = d.add_submission(&first, FileKind::Message, b"message".to_vec());
+        (submission, d.add_reply(&first, b"reply".to_vec()))
+    });
+    let events = watcher.poll(&client).unwrap();
+    assert_eq!(events.len(), 4);
+    assert_eq!(
+        events[0],
+        Event::StarChanged {
+            source: first,
+            starred: true,
+        }
+    );
+    match events[1] {
+        Event::NewSubmission(ref s) => assert_eq!(*s.uuid(), submission),
+        ref e => panic!("Unexpected event: {:?}", e),
+    }
+    match events[2] {
+        Event::ReplyAdded(ref r) => assert_eq!(*r.uuid(), reply),
+        ref e => panic!("Unexpected event: {:?}", e),
+    }
+    assert_eq!(events[3], Event::SourceDeleted(second));
+}
+
+#[test]
+fn spawn_sends_events() {
+    let (dataset, source) = common::dataset_with_source();
+    let (server, client) = common::start(dataset);
+
+    let mut watcher = Watcher::new().interval(Duration::from_millis(10));
+    watcher.baseline(&client).unwrap();
+    let handle = watcher.spawn(client);
+
+    let submission =
+        server.with_dataset(|d| d.add_submission(&source, FileKind::Document, b"doc".to_vec()));
+    match handle
+        .events()
+        .recv_timeout(Duration::from_secs(5))
+        .unwrap()
+        .unwrap()
+    {
+        Event::NewSubmission(ref s) => assert_eq!(*s.uuid(), submission),
+        ref e => panic!("Unexpected event: {:?}", e),
+    }
+    handle.stop();
+}
+
+#[test]
+fn stop_interrupts_interval() {
+    let (dataset, _) = common::dataset_with_source();
+    let (_server, client) = common::start(dataset);
+
+    // Polls that find nothing still see the stop request
+    let mut watcher = Watcher::new().interval(Duration::from_secs(3600));
+    watcher.baseline(&client).unwrap();
+    let handle = watcher.spawn(client);
+    thread::sleep(Duration::from_millis(100));
+
+    let started = Instant::now();
+    handle.stop();
+    assert!(started.elapsed() < Duration::from_secs(5));
+}